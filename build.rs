@@ -1,6 +1,10 @@
 use std::fmt::Write;
 use std::{env, path::Path};
 
+#[allow(dead_code)]
+#[path = "src/bm/nnue/format.rs"]
+mod format;
+
 fn main() {
     parse_bm_net();
 }
@@ -11,7 +15,26 @@ fn parse_bm_net() {
 
     let eval_path = Path::new(&out_dir).join("eval.bin");
     let nn_bytes = std::fs::read(&nn_dir).expect("nnue file doesn't exist");
-    let layers = parse_arch(&nn_bytes);
+    let nn_bytes = match nn_bytes.starts_with(b"version https://git-lfs") {
+        // Building without a trained network has to be asked for explicitly
+        true if env::var_os("EMPTY_NET").is_some() => {
+            println!("cargo:warning={nn_dir} is a Git LFS pointer, embedding an empty network");
+            empty_net()
+        }
+        true => panic!(
+            "{nn_dir} is a Git LFS pointer, run `git lfs pull` or set EVALFILE to a trained network. \
+            Set EMPTY_NET=1 to build with an empty network that evaluates every position as a draw"
+        ),
+        false => nn_bytes,
+    };
+    let (header, payload) = match format::read(&nn_bytes) {
+        Ok(net) => net,
+        Err(err) => panic!("{nn_dir} is not a valid network: {err}"),
+    };
+    if let Err(err) = header.check(format::FEATURES, header.mid, format::BUCKETS) {
+        panic!("{nn_dir} doesn't match the engine architecture: {err}");
+    }
+    let layers = [header.input, header.mid, header.output];
 
     let arch_path = Path::new(&out_dir).join("arch.rs");
     let mut def_nodes = String::new();
@@ -20,18 +43,25 @@ fn parse_bm_net() {
         writeln!(&mut def_nodes, "const {}: usize = {};", name, size).unwrap();
     }
 
-    std::fs::write(&eval_path, nn_bytes).unwrap();
+    // Legacy networks are embedded with an up to date header
+    let mut eval_bytes =
+        format::Header::new(header.input, header.mid, header.output, payload).to_bytes();
+    eval_bytes.extend_from_slice(payload);
+
+    std::fs::write(&eval_path, eval_bytes).unwrap();
     std::fs::write(&arch_path, def_nodes).unwrap();
 
     println!("cargo:rerun-if-env-changed=EVALFILE");
+    println!("cargo:rerun-if-env-changed=EMPTY_NET");
     println!("cargo:rerun-if-changed={nn_dir}");
+    println!("cargo:rerun-if-changed=src/bm/nnue/format.rs");
 }
 
-pub fn parse_arch(bytes: &[u8]) -> [usize; 3] {
-    let mut layers = [0; 3];
-    for (bytes, layer) in bytes.chunks(4).take(3).zip(&mut layers) {
-        let bytes = bytes.try_into().unwrap();
-        *layer = u32::from_le_bytes(bytes) as usize;
-    }
-    layers
+/// Network without a hidden layer, evaluates every position as a draw
+fn empty_net() -> Vec<u8> {
+    let header = format::Header::new(format::FEATURES, 0, format::BUCKETS, &[]);
+    let payload = vec![0; header.payload_size().unwrap()];
+    let mut bytes = format::Header::new(format::FEATURES, 0, format::BUCKETS, &payload).to_bytes();
+    bytes.extend_from_slice(&payload);
+    bytes
 }
@@ -1,7 +1,7 @@
 use std::{
     fs::OpenOptions,
     io::{BufWriter, Write},
-    sync::{mpsc::channel, Arc},
+    sync::{mpsc::channel, Arc, Mutex},
     time::{Duration, Instant},
 };
 
@@ -12,7 +12,7 @@ use rand::Rng;
 use crate::bm::{
     bm_runner::{
         ab_runner::AbRunner,
-        config::{NoInfo, Run},
+        config::{NoObserver, Run},
         time::{TimeManagementInfo, TimeManager},
     },
     bm_util::eval::Evaluation,
@@ -38,7 +38,7 @@ fn play_single(
             cozy_chess::GameStatus::Ongoing => {}
         }
         time_manager.initiate(engine.get_board(), time_management_info);
-        let (mut make_move, eval, _, _) = engine.search::<Run, NoInfo>();
+        let (mut make_move, _, eval, _, _) = engine.search::<Run>(Arc::new(Mutex::new(NoObserver)));
         time_manager.clear();
         let turn = match engine.get_board().side_to_move() {
             cozy_chess::Color::White => 1,
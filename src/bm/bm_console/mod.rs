@@ -1,4 +1,6 @@
+use super::json::JsonAdapter;
 use super::uci::UciAdapter;
+use super::xboard::XBoardAdapter;
 
 #[cfg(feature = "data")]
 mod gen_eval;
@@ -6,14 +8,27 @@ mod gen_eval;
 mod gen_fen;
 #[cfg(feature = "trace")]
 mod grad;
+/// Protocol the console speaks
+enum Protocol {
+    Uci(UciAdapter),
+    Json(JsonAdapter),
+    XBoard(XBoardAdapter),
+}
+
 pub struct BmConsole {
-    uci: UciAdapter,
+    /// Selected by the first command unless given at startup
+    protocol: Option<Protocol>,
 }
 
 impl BmConsole {
     pub fn new() -> Self {
+        Self { protocol: None }
+    }
+
+    /// Console reading JSON-lines requests, see [crate::bm::json]
+    pub fn json() -> Self {
         Self {
-            uci: UciAdapter::new(),
+            protocol: Some(Protocol::Json(JsonAdapter::new())),
         }
     }
 
@@ -34,7 +49,15 @@ impl BmConsole {
             }
             return true;
         }
-        self.uci.input(command)
+        let protocol = self.protocol.get_or_insert_with(|| match command.trim() {
+            "xboard" => Protocol::XBoard(XBoardAdapter::new()),
+            _ => Protocol::Uci(UciAdapter::new()),
+        });
+        match protocol {
+            Protocol::Uci(uci) => uci.input(command),
+            Protocol::Json(json) => json.input(command),
+            Protocol::XBoard(xboard) => xboard.input(command),
+        }
     }
 
     #[cfg(feature = "data")]
@@ -1,25 +1,40 @@
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use cozy_chess::{Board, Color, Move, Piece, Square};
 
-use crate::bm::bm_runner::config::{GuiInfo, NoInfo, SearchMode, SearchStats};
+use crate::bm::bm_runner::config::{
+    NoObserver, SearchMode, SearchStats, SearchUpdate, SharedObserver,
+};
 use crate::bm::bm_search::move_entry::MoveEntry;
 use crate::bm::bm_search::search;
 use crate::bm::bm_search::search::Pv;
+use crate::bm::bm_util::endgame;
 use crate::bm::bm_util::eval::Evaluation;
 use crate::bm::bm_util::history::History;
 use crate::bm::bm_util::lookup::LookUp2d;
-use crate::bm::bm_util::position::Position;
-use crate::bm::bm_util::t_table::TranspositionTable;
+use crate::bm::bm_util::position::{Aggression, Position};
+use crate::bm::bm_util::t_table::{Bounds, TranspositionTable};
 use crate::bm::bm_util::window::Window;
+use crate::bm::nnue::Nnue;
+use crate::bm::tablebase::{self, Tablebase, Wdl};
 use crate::bm::uci;
 
+use super::skill::{Rng, Skill, MAX_LEVEL};
 use super::time::TimeManager;
 
 pub const MAX_PLY: u32 = 128;
 
+/// Root moves are only reported once the search has taken this long
+const CUR_MOVE_DELAY: Duration = Duration::from_secs(3);
+/// Aspiration window failures are only reported once the search has taken this long
+const BOUND_INFO_DELAY: Duration = Duration::from_secs(1);
+/// Time between progress reports that don't depend on iterations completing
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+/// Seed of the skill level move choice in deterministic mode
+const DETERMINISTIC_SEED: u64 = 0x5EED;
+
 #[derive(Debug, Clone)]
 pub struct NodeCounter {
     node_counters: Vec<Option<Arc<AtomicU64>>>,
@@ -66,6 +81,39 @@ pub struct SharedContext {
     t_table: Arc<TranspositionTable>,
     lmr_lookup: Arc<LmrLookup>,
     lmp_lookup: Arc<LmpLookup>,
+
+    /// Root moves the search is restricted to, empty if all moves are allowed
+    search_moves: Vec<Move>,
+
+    tablebase: Option<Arc<dyn Tablebase>>,
+    tb_hits: Arc<AtomicU64>,
+
+    /// Progress reporting, only set for the main thread
+    main_thread_info: Option<MainThreadInfo>,
+
+    aggression: Aggression,
+    /// Centipawns the root side to move loses by drawing
+    contempt: i16,
+}
+
+/// Progress reporting of the main thread
+#[derive(Clone)]
+struct MainThreadInfo {
+    observer: SharedObserver,
+    node_counter: NodeCounter,
+    /// Milliseconds since search start of the last heartbeat
+    last_heartbeat: Arc<AtomicU64>,
+    chess960: bool,
+}
+
+impl std::fmt::Debug for MainThreadInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MainThreadInfo")
+            .field("node_counter", &self.node_counter)
+            .field("last_heartbeat", &self.last_heartbeat)
+            .field("chess960", &self.chess960)
+            .finish_non_exhaustive()
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -132,18 +180,51 @@ pub struct ThreadContext {
     pub abort: bool,
     /// Used for node based time management
     pub root_nodes: [[u64; Square::NUM]; Square::NUM],
+    /// Root moves that are skipped, used to search secondary MultiPV lines
+    pub excluded_moves: Vec<Move>,
+    /// Latest root move and score of each MultiPV line
+    pub root_lines: Vec<(Move, Evaluation)>,
 }
 
 impl SharedContext {
+    /// Checks the time every 1024 nodes, also emitting the main thread heartbeat
     pub fn abort_search(&self, node_cnt: u64) -> bool {
         if node_cnt % 1024 != 0 {
             return false;
         }
-        self.time_manager.abort_search(self.start, node_cnt)
+        self.heartbeat();
+        self.time_manager
+            .abort_search(self.start, node_cnt, self.total_nodes(node_cnt))
+    }
+
+    /// Nodes searched by all threads, helper threads only know their own count
+    fn total_nodes(&self, nodes: u64) -> u64 {
+        self.main_thread_info
+            .as_ref()
+            .map_or(nodes, |info| info.node_counter.get_node_count())
+    }
+
+    /// Reports nodes, speed and table usage every [HEARTBEAT_INTERVAL]
+    fn heartbeat(&self) {
+        let Some(info) = &self.main_thread_info else {
+            return;
+        };
+        let elapsed = self.start.elapsed();
+        let last = Duration::from_millis(info.last_heartbeat.load(Ordering::Relaxed));
+        if elapsed >= last + HEARTBEAT_INTERVAL {
+            info.last_heartbeat
+                .store(elapsed.as_millis() as u64, Ordering::Relaxed);
+            info.observer.lock().unwrap().progress(
+                elapsed,
+                info.node_counter.get_node_count(),
+                self.t_table.hashfull(),
+            );
+        }
     }
 
     fn abort_deepening(&self, depth: u32, nodes: u64) -> bool {
-        self.time_manager.abort_deepening(self.start, depth, nodes)
+        self.time_manager
+            .abort_deepening(self.start, depth, nodes, self.total_nodes(nodes))
     }
 
     /// Shared transposition table
@@ -156,13 +237,91 @@ impl SharedContext {
         &self.lmr_lookup
     }
 
+    pub fn get_aggression(&self) -> Aggression {
+        self.aggression
+    }
+
+    /// Side to move relative score of a draw
+    /// - Draws are worse than equality for the side to move at root
+    pub fn draw_score(&self, root_stm: Color, stm: Color) -> Evaluation {
+        Evaluation::new(match stm == root_stm {
+            true => -self.contempt,
+            false => self.contempt,
+        })
+    }
+
     /// Late move pruning look up values
     pub fn get_lmp_lookup(&self) -> &LmpLookup {
         &self.lmp_lookup
     }
+
+    /// Returns true if the root move is allowed by `go searchmoves`
+    pub fn is_search_move(&self, make_move: Move) -> bool {
+        self.search_moves.is_empty() || self.search_moves.contains(&make_move)
+    }
+
+    /// Returns true if the root search is restricted to a subset of legal moves
+    pub fn restricted_root(&self) -> bool {
+        !self.search_moves.is_empty()
+    }
+
+    /// Reports the root move being searched, once the search has run for [CUR_MOVE_DELAY]
+    pub fn report_cur_move(&self, board: &Board, depth: u32, make_move: Move, move_number: usize) {
+        let Some(info) = &self.main_thread_info else {
+            return;
+        };
+        if self.start.elapsed() >= CUR_MOVE_DELAY {
+            let mut uci_move = make_move;
+            uci::convert_move_to_uci(&mut uci_move, board, info.chess960);
+            info.observer
+                .lock()
+                .unwrap()
+                .current_move(depth, uci_move, move_number);
+        }
+    }
+
+    /// Probes the tablebase if the position is covered, counting successful probes
+    pub fn probe_wdl(&self, board: &Board) -> Option<Wdl> {
+        let tablebase = self.tablebase.as_deref()?;
+        if !tablebase::probeable(tablebase, board) {
+            return None;
+        }
+        let wdl = tablebase.probe_wdl(board)?;
+        self.tb_hits.fetch_add(1, Ordering::Relaxed);
+        Some(wdl)
+    }
 }
 
 impl ThreadContext {
+    fn new(eval: Evaluation) -> Self {
+        Self {
+            window: Window::new(15, 45, 100, 9),
+            tt_hits: 0,
+            tt_misses: 0,
+            eval,
+            ss: vec![
+                SearchStack {
+                    eval: Evaluation::new(0),
+                    skip_move: None,
+                    move_played: None,
+                    pv: [None; MAX_PLY as usize + 1],
+                    pv_len: 0,
+                    aggr: 0
+                };
+                MAX_PLY as usize + 1
+            ],
+            sel_depth: 0,
+            history: History::new(),
+            killer_moves: vec![MoveEntry::new(); MAX_PLY as usize + 1],
+            nodes: Nodes(Arc::new(AtomicU64::new(0))),
+            abort: false,
+            stm: Color::White,
+            root_nodes: [[0; Square::NUM]; Square::NUM],
+            excluded_moves: vec![],
+            root_lines: vec![],
+        }
+    }
+
     pub fn increment_nodes(&self) {
         self.nodes.0.fetch_add(1, Ordering::Relaxed);
     }
@@ -183,23 +342,40 @@ impl ThreadContext {
         self.window.reset();
         self.sel_depth = 0;
         self.root_nodes = [[0; Square::NUM]; Square::NUM];
+        self.excluded_moves.clear();
+        self.root_lines.clear();
         self.nodes.0.store(0, Ordering::Relaxed);
     }
+
+    /// Returns true if the root move is excluded from the current MultiPV line
+    pub fn is_excluded(&self, make_move: Move) -> bool {
+        self.excluded_moves.contains(&make_move)
+    }
 }
 
-fn remove_aggression(eval: Evaluation, scale: i32) -> Evaluation {
-    const MAX: i32 = 200;
+fn remove_aggression(eval: Evaluation, scale: i32, max: i32) -> Evaluation {
     match eval.is_mate() {
         true => eval,
         false => {
             let eval = eval.raw() as i32;
-            let eval = eval - scale * eval.clamp(-MAX - scale, MAX + scale) / (100 + scale);
+            let eval = eval - scale * eval.clamp(-max - scale, max + scale) / (100 + scale);
             Evaluation::new(eval as i16)
         }
     }
 }
 
-fn to_wld(eval: Evaluation) -> (i16, i16, i16) {
+/// Removes the aggression bonus of the root position from a search score
+fn reported_eval(board: &Board, aggression: Aggression, eval: Evaluation) -> Evaluation {
+    let scale = board.occupied().len() - board.pieces(Piece::Pawn).len();
+    remove_aggression(
+        eval,
+        scale as i32 * aggression.scale as i32,
+        aggression.limit as i32,
+    )
+}
+
+/// Win, draw and loss chances in permille
+fn to_wdl(eval: Evaluation) -> (u16, u16, u16) {
     if let Some(mate_in) = eval.mate_in() {
         return match mate_in {
             _ if mate_in > 0 => (1000, 0, 0),
@@ -214,9 +390,9 @@ fn to_wld(eval: Evaluation) -> (i16, i16, i16) {
     wdl.iter_mut().for_each(|x| *x *= 1000.0 / sum);
 
     (
-        wdl[0] as i16,
-        wdl[1] as i16,
-        1000 - (wdl[0] as i16 + wdl[1] as i16),
+        wdl[0] as u16,
+        1000 - (wdl[0] as u16 + wdl[1] as u16),
+        wdl[1] as u16,
     )
 }
 
@@ -227,19 +403,55 @@ pub struct AbRunner {
     position: Position,
     chess960: bool,
     show_wdl: bool,
+    multi_pv: usize,
     thread_contexts: Vec<Arc<Mutex<ThreadContext>>>,
+    /// Every search starts from a clean state on the main thread only
+    deterministic: bool,
+    skill: Skill,
+    /// Used for the move choice of limited skill levels
+    rng: Rng,
+}
+
+/// MultiPV line completed during an iteration
+#[derive(Debug, Clone)]
+struct RootLine {
+    score: Evaluation,
+    /// Only inexact if the line ended on a mate score outside the aspiration window
+    bound: Bounds,
+    sel_depth: u32,
+    /// Principal variation starting with the root move
+    pv: Vec<Move>,
+}
+
+/// Converts a root PV to UCI notation, limiting its length to the search depth
+fn root_pv(position: &mut Position, moves: &[Move], chess960: bool, depth: u32) -> Vec<Move> {
+    let mut pv = vec![];
+    for &make_move in moves {
+        let mut uci_move = make_move;
+        uci::convert_move_to_uci(&mut uci_move, position.board(), chess960);
+        position.make_move(make_move);
+        pv.push(uci_move);
+        if pv.len() > depth as usize {
+            break;
+        }
+    }
+    for _ in 0..pv.len() {
+        position.unmake_move()
+    }
+    pv
 }
 
 impl AbRunner {
-    fn launch_searcher<SM: 'static + SearchMode + Send, Info: 'static + GuiInfo + Send>(
+    fn launch_searcher<SM: 'static + SearchMode + Send>(
         &mut self,
+        observer: SharedObserver,
         local_context: Arc<Mutex<ThreadContext>>,
         thread: usize,
         chess960: bool,
         show_wdl: bool,
-    ) -> impl FnMut() -> (Option<Move>, Evaluation, u32, u64) {
+    ) -> impl FnMut() -> (Option<Move>, Option<Move>, Evaluation, u32, u64) {
         let main_thread = thread == 0;
-        let shared_context = self.shared_context.clone();
+        let mut shared_context = self.shared_context.clone();
 
         self.node_counter.add_node_counter(
             thread as usize,
@@ -250,9 +462,26 @@ impl AbRunner {
         } else {
             None
         };
+        shared_context.main_thread_info = node_counter.clone().map(|node_counter| MainThreadInfo {
+            observer: observer.clone(),
+            node_counter,
+            last_heartbeat: Arc::new(AtomicU64::new(0)),
+            chess960,
+        });
         let mut position = self.position.clone();
         let mut debugger = SM::new(self.position.board());
-        let gui_info = Info::new();
+
+        let mut root_move_cnt = 0;
+        position.board().generate_moves(|piece_moves| {
+            root_move_cnt += piece_moves
+                .into_iter()
+                .filter(|&make_move| shared_context.is_search_move(make_move))
+                .count();
+            false
+        });
+        let multi_pv = self.skill.multi_pv(self.multi_pv).min(root_move_cnt).max(1);
+        // Skill levels search extra lines for their move choice without reporting them
+        let reported_lines = self.multi_pv.min(multi_pv);
         move || {
             let mut local_context = local_context.lock().unwrap();
 
@@ -261,66 +490,167 @@ impl AbRunner {
             local_context.stm = position.board().side_to_move();
             let start_time = Instant::now();
             let mut best_move = None;
+            let mut ponder_move = None;
             let mut eval: Option<Evaluation> = None;
             let mut depth = 1_u32;
             let mut abort = false;
+
+            // Each MultiPV line keeps its own aspiration window and score
+            let mut windows = vec![local_context.window.clone(); multi_pv];
+            let mut line_evals: Vec<Option<Evaluation>> = vec![None; multi_pv];
+            // Lines of the last completed iteration, sorted by score
+            let mut lines: Vec<RootLine> = vec![];
+            let report = |position: &mut Position,
+                          sel_depth: u32,
+                          pv: &[Move],
+                          pv_index: usize,
+                          depth: u32,
+                          score: Evaluation,
+                          bound: Bounds| {
+                let pv = root_pv(position, pv, chess960, depth);
+                let total_nodes = node_counter.as_ref().unwrap().get_node_count();
+                let hashfull = shared_context.t_table.hashfull();
+                let tb_hits = shared_context.tb_hits.load(Ordering::Relaxed);
+                let line_eval = reported_eval(position.board(), shared_context.aggression, score);
+                let wdl = match show_wdl {
+                    true => Some(to_wdl(line_eval)),
+                    false => None,
+                };
+                observer.lock().unwrap().update(&SearchUpdate {
+                    depth,
+                    sel_depth,
+                    multi_pv: pv_index + 1,
+                    score: line_eval.into(),
+                    bound,
+                    wdl,
+                    time: start_time.elapsed(),
+                    nodes: total_nodes,
+                    hashfull,
+                    tb_hits,
+                    pv,
+                });
+            };
             'outer: loop {
-                let mut fail_cnt = 0;
-                local_context.window.reset();
-                loop {
-                    if abort {
-                        break 'outer;
-                    }
-                    let (alpha, beta) = if eval.is_some()
-                        && eval.unwrap().raw().abs() < 1000
-                        && depth > 4
-                        && fail_cnt < 10
-                    {
-                        local_context.window.get()
-                    } else {
-                        (Evaluation::min(), Evaluation::max())
-                    };
-                    local_context.sel_depth = 0;
-                    let score = search::search::<Pv>(
-                        &mut position,
-                        &mut local_context,
-                        &shared_context,
-                        0,
-                        depth,
-                        alpha,
-                        beta,
-                        false,
-                    );
-                    nodes = local_context.nodes();
-                    if depth > 1 && local_context.abort {
-                        break 'outer;
-                    }
-                    local_context.window.set(score);
-                    local_context.eval = score;
+                local_context.excluded_moves.clear();
+                let previous_lines = std::mem::take(&mut lines);
+                for pv_index in 0..multi_pv {
+                    let mut fail_cnt = 0;
+                    // Only searches that raise alpha at the root set the PV of the line
+                    let mut line_pv: Vec<Move> = vec![];
+                    windows[pv_index].reset();
+                    let (score, bound) = loop {
+                        if abort {
+                            break 'outer;
+                        }
+                        let line_eval = line_evals[pv_index];
+                        let (alpha, beta) = if line_eval.is_some()
+                            && line_eval.unwrap().raw().abs() < 1000
+                            && depth > 4
+                            && fail_cnt < 10
+                        {
+                            windows[pv_index].get()
+                        } else {
+                            (Evaluation::min(), Evaluation::max())
+                        };
+                        local_context.sel_depth = 0;
+                        let score = search::search::<Pv>(
+                            &mut position,
+                            &mut local_context,
+                            &shared_context,
+                            0,
+                            depth,
+                            alpha,
+                            beta,
+                            false,
+                        );
+                        nodes = local_context.nodes();
+                        if depth > 1 && local_context.abort {
+                            break 'outer;
+                        }
+                        windows[pv_index].set(score);
+                        let bound = match score {
+                            _ if score <= alpha => Bounds::UpperBound,
+                            _ if score >= beta => Bounds::LowerBound,
+                            _ => Bounds::Exact,
+                        };
+                        let root_stack = &local_context.ss[0];
+                        if root_stack.pv_len > 0 {
+                            line_pv = root_stack.pv[..root_stack.pv_len]
+                                .iter()
+                                .map_while(|&make_move| make_move)
+                                .collect();
+                        }
 
-                    let root_move = local_context.ss[0].pv[0].unwrap();
-                    shared_context.time_manager.deepen(
-                        thread,
-                        depth,
-                        local_context.root_nodes[root_move.from as usize][root_move.to as usize],
-                        nodes,
-                        local_context.eval,
-                        root_move,
-                    );
-                    if (score > alpha && score < beta) || score.is_mate() {
-                        abort = shared_context.abort_deepening(depth, nodes);
-                        best_move = local_context.ss[0].pv[0];
-                        eval = Some(score);
-                        break;
-                    } else {
+                        if pv_index == 0 {
+                            local_context.eval = score;
+                            if let Some(root_move) = line_pv.first().copied().or(best_move) {
+                                shared_context.time_manager.deepen(
+                                    thread,
+                                    depth,
+                                    local_context.root_nodes[root_move.from as usize]
+                                        [root_move.to as usize],
+                                    nodes,
+                                    local_context.eval,
+                                    root_move,
+                                );
+                            }
+                        }
+                        if bound == Bounds::Exact || (score.is_mate() && !line_pv.is_empty()) {
+                            if pv_index == 0 {
+                                best_move = line_pv.first().copied();
+                                ponder_move = line_pv.get(1).copied();
+                                eval = Some(score);
+                            }
+                            break (score, bound);
+                        }
                         fail_cnt += 1;
-                        if score <= alpha {
-                            local_context.window.fail_low();
+                        if bound == Bounds::UpperBound {
+                            windows[pv_index].fail_low();
                         } else {
-                            local_context.window.fail_high();
+                            windows[pv_index].fail_high();
+                            // Play the fail high move if time runs out during the re-search
+                            if pv_index == 0 {
+                                best_move = line_pv.first().copied();
+                                ponder_move = line_pv.get(1).copied();
+                            }
                         }
-                    }
+                        if main_thread
+                            && pv_index < reported_lines
+                            && start_time.elapsed() >= BOUND_INFO_DELAY
+                        {
+                            // A fail low doesn't have a PV, show the line of the last iteration
+                            let pv = match line_pv.is_empty() {
+                                true => previous_lines
+                                    .get(pv_index)
+                                    .map_or(&[][..], |line| &line.pv),
+                                false => &line_pv,
+                            };
+                            let sel_depth = local_context.sel_depth;
+                            report(&mut position, sel_depth, pv, pv_index, depth, score, bound);
+                        }
+                    };
+                    let Some(&root_move) = line_pv.first() else {
+                        break 'outer;
+                    };
+                    local_context.excluded_moves.push(root_move);
+                    lines.push(RootLine {
+                        score,
+                        bound,
+                        sel_depth: local_context.sel_depth,
+                        pv: line_pv,
+                    });
                 }
+                // Lines searched later can score higher than earlier ones
+                lines.sort_by_key(|line| std::cmp::Reverse(line.score));
+                for (index, line) in lines.iter().enumerate() {
+                    windows[index].set(line.score);
+                    line_evals[index] = Some(line.score);
+                }
+                best_move = lines[0].pv.first().copied();
+                ponder_move = lines[0].pv.get(1).copied();
+                eval = Some(lines[0].score);
+                local_context.root_lines =
+                    lines.iter().map(|line| (line.pv[0], line.score)).collect();
                 if main_thread {
                     debugger.push(SearchStats::new(
                         start_time.elapsed().as_millis(),
@@ -328,43 +658,20 @@ impl AbRunner {
                         eval,
                         best_move,
                     ));
-
-                    let mut pv = vec![];
-                    let root_stack = &local_context.ss[0];
-                    for make_move in &root_stack.pv[..root_stack.pv_len] {
-                        if let Some(make_move) = *make_move {
-                            let mut uci_move = make_move;
-                            uci::convert_move_to_uci(&mut uci_move, position.board(), chess960);
-                            position.make_move(make_move);
-                            pv.push(uci_move);
-                            if pv.len() > depth as usize {
-                                break;
-                            }
-                        } else {
-                            break;
-                        }
-                    }
-                    for _ in 0..pv.len() {
-                        position.unmake_move()
+                    for (index, line) in lines.iter().enumerate().take(reported_lines) {
+                        report(
+                            &mut position,
+                            line.sel_depth,
+                            &line.pv,
+                            index,
+                            depth,
+                            line.score,
+                            line.bound,
+                        );
                     }
-                    let total_nodes = node_counter.as_ref().unwrap().get_node_count();
-                    let scale = position.board().occupied().len()
-                        - position.board().pieces(Piece::Pawn).len();
-                    let eval = remove_aggression(eval.unwrap(), scale as i32 * 2);
-                    let wld = match show_wdl {
-                        true => Some(to_wld(eval)),
-                        false => None,
-                    };
-                    gui_info.print_info(
-                        local_context.sel_depth,
-                        depth,
-                        eval,
-                        wld,
-                        start_time.elapsed(),
-                        total_nodes,
-                        &pv,
-                    );
                 }
+                abort = shared_context.abort_deepening(depth, nodes)
+                    || eval.is_some_and(|eval| shared_context.time_manager.mate_found(eval));
 
                 depth += 1;
                 if depth > 1 && shared_context.abort_deepening(depth, nodes) {
@@ -373,7 +680,8 @@ impl AbRunner {
             }
             if let Some(evaluation) = eval {
                 debugger.complete();
-                (best_move, evaluation, depth, nodes)
+                // The search always ends during or right before the iteration at `depth`
+                (best_move, ponder_move, evaluation, depth - 1, nodes)
             } else {
                 panic!("# Search function has failed to evaluate the position");
             }
@@ -381,6 +689,7 @@ impl AbRunner {
     }
 
     pub fn new(board: Board, time_manager: Arc<TimeManager>) -> Self {
+        endgame::init();
         let mut position = Position::new(board);
         Self {
             node_counter: NodeCounter {
@@ -404,48 +713,55 @@ impl AbRunner {
                     x as usize
                 })),
                 start: Instant::now(),
+                search_moves: vec![],
+                tablebase: None,
+                tb_hits: Arc::new(AtomicU64::new(0)),
+                main_thread_info: None,
+                aggression: Aggression::default(),
+                contempt: 0,
             },
-            main_thread_context: Arc::new(Mutex::new(ThreadContext {
-                window: Window::new(15, 45, 100, 9),
-                tt_hits: 0,
-                tt_misses: 0,
-                eval: position.get_eval(),
-                ss: vec![
-                    SearchStack {
-                        eval: Evaluation::new(0),
-                        skip_move: None,
-                        move_played: None,
-                        pv: [None; MAX_PLY as usize + 1],
-                        pv_len: 0,
-                        aggr: 0
-                    };
-                    MAX_PLY as usize + 1
-                ],
-                sel_depth: 0,
-                history: History::new(),
-                killer_moves: vec![MoveEntry::new(); MAX_PLY as usize + 1],
-                nodes: Nodes(Arc::new(AtomicU64::new(0))),
-                abort: false,
-                stm: Color::White,
-                root_nodes: [[0; Square::NUM]; Square::NUM],
-            })),
+            main_thread_context: Arc::new(Mutex::new(ThreadContext::new(position.get_eval()))),
             thread_contexts: vec![],
             position,
             chess960: false,
             show_wdl: false,
+            multi_pv: 1,
+            deterministic: false,
+            skill: Skill::from_level(MAX_LEVEL),
+            rng: Rng::from_time(),
         }
     }
 
-    pub fn search<SM: 'static + SearchMode + Send, Info: 'static + GuiInfo + Send>(
+    /// Returns the best move, the expected reply, evaluation, completed depth and node count
+    /// - Only the main thread reports to the observer, completion is left to the caller
+    pub fn search<SM: 'static + SearchMode + Send>(
         &mut self,
-    ) -> (Move, Evaluation, u32, u64) {
-        let thread_count = self.thread_contexts.len() + 1;
+        observer: SharedObserver,
+    ) -> (Move, Option<Move>, Evaluation, u32, u64) {
+        let helper_contexts = match self.deterministic {
+            true => {
+                self.reset_search_state();
+                vec![]
+            }
+            false => self.thread_contexts.clone(),
+        };
+        let thread_count = helper_contexts.len() + 1;
         let mut join_handlers = vec![];
         self.shared_context.start = Instant::now();
         self.node_counter.initialize_node_counters(thread_count);
         self.position.reset();
-        for (i, context) in self.thread_contexts.clone().iter().enumerate() {
-            join_handlers.push(std::thread::spawn(self.launch_searcher::<SM, NoInfo>(
+        self.shared_context.tb_hits.store(0, Ordering::Relaxed);
+        if self.skill.enabled() {
+            let node_limit = self.skill.node_limit();
+            self.shared_context.time_manager.limit_nodes(node_limit);
+        }
+        let search_moves = self.shared_context.search_moves.clone();
+        if let Some(tb_moves) = self.tablebase_root_moves() {
+            self.shared_context.search_moves = tb_moves;
+        }
+        for (i, context) in helper_contexts.iter().enumerate() {
+            join_handlers.push(std::thread::spawn(self.launch_searcher::<SM>(
+                Arc::new(Mutex::new(NoObserver)),
                 context.clone(),
                 i + 1,
                 self.chess960,
@@ -453,26 +769,79 @@ impl AbRunner {
             )));
         }
 
-        let (final_move, final_eval, max_depth, mut node_count) = self.launch_searcher::<SM, Info>(
-            self.main_thread_context.clone(),
-            0,
-            self.chess960,
-            self.show_wdl,
-        )();
+        let (mut final_move, mut ponder_move, mut final_eval, max_depth, mut node_count) =
+            self.launch_searcher::<SM>(
+                observer,
+                self.main_thread_context.clone(),
+                0,
+                self.chess960,
+                self.show_wdl,
+            )();
+        self.shared_context.time_manager.finish();
         for join_handler in join_handlers {
-            let (_, _, _, nodes) = join_handler.join().unwrap();
+            let (_, _, _, _, nodes) = join_handler.join().unwrap();
             node_count += nodes;
         }
+        self.shared_context.search_moves = search_moves;
+        self.shared_context.time_manager.nodes_searched(node_count);
         if final_move.is_none() {
             panic!("# All move generation has failed");
         }
+        if self.skill.enabled() {
+            let root_lines = self.main_thread_context.lock().unwrap().root_lines.clone();
+            if let Some((make_move, eval)) = self.skill.pick(&root_lines, &mut self.rng) {
+                if Some(make_move) != final_move {
+                    ponder_move = None;
+                }
+                final_move = Some(make_move);
+                final_eval = eval;
+            }
+        }
+        let final_move = final_move.unwrap();
+        let ponder_move = ponder_move.or_else(|| self.tt_reply(final_move));
         self.shared_context.t_table.age();
-        (final_move.unwrap(), final_eval, max_depth, node_count)
+        (final_move, ponder_move, final_eval, max_depth, node_count)
+    }
+
+    /// Root moves that keep the tablebase result, restricted to the moves allowed by `go searchmoves`
+    /// - Returns None if the position isn't covered or no allowed move keeps the result
+    fn tablebase_root_moves(&self) -> Option<Vec<Move>> {
+        let tablebase = self.shared_context.tablebase.as_deref()?;
+        let moves: Vec<Move> = tablebase::root_moves(tablebase, self.position.board())?
+            .into_iter()
+            .filter(|&make_move| self.shared_context.is_search_move(make_move))
+            .collect();
+        self.shared_context.tb_hits.fetch_add(1, Ordering::Relaxed);
+        match moves.is_empty() {
+            true => None,
+            false => Some(moves),
+        }
+    }
+
+    /// Looks up the expected reply to a move in the transposition table
+    fn tt_reply(&self, make_move: Move) -> Option<Move> {
+        let mut board = self.position.board().clone();
+        board.play_unchecked(make_move);
+        self.shared_context
+            .t_table
+            .get(&board)
+            .and_then(|entry| entry.table_move)
+            .filter(|&reply| board.is_legal(reply))
     }
 
-    pub fn hash(&mut self, hash_mb: usize) {
+    /// Resizes the transposition table, keeping existing entries if `rehash` is set
+    pub fn hash(&mut self, hash_mb: usize, rehash: bool) {
         let entry_count = hash_mb as u64 * 1024 * 1024 / 12;
-        self.shared_context.t_table = Arc::new(TranspositionTable::new(entry_count as usize));
+        let t_table =
+            self.shared_context
+                .t_table
+                .resize(entry_count as usize, self.threads(), rehash);
+        self.shared_context.t_table = Arc::new(t_table);
+    }
+
+    /// Number of search threads, used to split table maintenance
+    fn threads(&self) -> usize {
+        self.thread_contexts.len() + 1
     }
 
     pub fn set_threads(&mut self, threads: u16) {
@@ -482,24 +851,80 @@ impl AbRunner {
             .collect();
     }
 
+    /// Replaces the neural network used by all threads
+    pub fn set_nnue(&mut self, nnue: Nnue) {
+        self.position.set_evaluator(nnue);
+    }
+
+    /// Sets the tablebase probed during search, None disables probing
+    pub fn set_tablebase(&mut self, tablebase: Option<Arc<dyn Tablebase>>) {
+        self.shared_context.tablebase = tablebase;
+    }
+
+    /// Search score of the current position as it is reported during search
+    pub fn reported_eval(&self, eval: Evaluation) -> Evaluation {
+        reported_eval(self.position.board(), self.shared_context.aggression, eval)
+    }
+
     pub fn raw_eval(&mut self) -> Evaluation {
         self.position.get_eval()
     }
 
+    /// Discards history, killers and transposition table entries of previous searches
+    fn reset_search_state(&mut self) {
+        *self.main_thread_context.lock().unwrap() = ThreadContext::new(self.position.get_eval());
+        self.clear_hash();
+        self.rng = Rng::new(DETERMINISTIC_SEED);
+    }
+
+    /// Sets the centipawns the root side to move loses by drawing
+    pub fn set_contempt(&mut self, contempt: i16) {
+        self.shared_context.contempt = contempt;
+    }
+
+    pub fn set_aggression(&mut self, aggression: Aggression) {
+        self.shared_context.aggression = aggression;
+    }
+
+    /// Limits playing strength, see [Skill]
+    pub fn set_skill(&mut self, skill: Skill) {
+        self.skill = skill;
+    }
+
+    /// Makes results depend only on the position and search limits
+    /// - Searches run on the main thread only, the `Threads` setting is ignored
+    /// - Every search starts from a clean state
+    /// - Time limited searches still depend on the speed of the machine
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+
     pub fn new_game(&self) {
-        self.shared_context.t_table.clean();
+        self.clear_hash();
+    }
+
+    pub fn clear_hash(&self) {
+        self.shared_context.t_table.clean(self.threads());
     }
 
     pub fn set_board(&mut self, board: Board) {
+        self.shared_context.search_moves.clear();
         self.position.set_board(board);
     }
 
     pub fn make_move(&mut self, make_move: Move) {
+        self.shared_context.search_moves.clear();
         self.position.make_move(make_move);
         self.position.reset();
     }
 
-    #[cfg(feature = "data")]
+    /// Restricts the next search to the given root moves
+    /// - Moves are expected to be legal in the current position
+    /// - An empty list allows all moves
+    pub fn set_search_moves(&mut self, search_moves: Vec<Move>) {
+        self.shared_context.search_moves = search_moves;
+    }
+
     pub fn get_position(&self) -> &Position {
         &self.position
     }
@@ -515,4 +940,74 @@ impl AbRunner {
     pub fn set_uci_show_wdl(&mut self, show_wdl: bool) {
         self.show_wdl = show_wdl;
     }
+
+    /// Sets the number of principal variations reported at each depth
+    pub fn set_multi_pv(&mut self, multi_pv: usize) {
+        self.multi_pv = multi_pv.max(1);
+    }
+}
+
+#[test]
+fn deterministic_search() {
+    use super::config::Run;
+    use super::time::TimeManagementInfo;
+
+    crate::bm::with_search_stack(|| {
+        let time_manager = Arc::new(TimeManager::new());
+        let search = |runner: &mut AbRunner, fen: &str| {
+            let board: Board = fen.parse().unwrap();
+            runner.set_board(board.clone());
+            time_manager.initiate(&board, &[TimeManagementInfo::MaxNodes(20_000)]);
+            let (make_move, _, eval, depth, nodes) =
+                runner.search::<Run>(Arc::new(Mutex::new(NoObserver)));
+            time_manager.clear();
+            (make_move, eval, depth, nodes)
+        };
+        let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let startpos = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+        let mut single = AbRunner::new(Board::default(), time_manager.clone());
+        single.set_deterministic(true);
+        let first = search(&mut single, kiwipete);
+        search(&mut single, startpos);
+        assert_eq!(search(&mut single, kiwipete), first);
+
+        // Helper threads are ignored, the search matches a single threaded one node for node
+        let mut threaded = AbRunner::new(Board::default(), time_manager.clone());
+        threaded.set_threads(2);
+        threaded.set_deterministic(true);
+        search(&mut threaded, startpos);
+        assert_eq!(search(&mut threaded, kiwipete), first);
+    });
+}
+
+#[test]
+fn multi_pv_order() {
+    use super::config::{Run, Score};
+    use super::time::TimeManagementInfo;
+
+    crate::bm::with_search_stack(|| {
+        let time_manager = Arc::new(TimeManager::new());
+        let board: Board = "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1".parse().unwrap();
+        let mut runner = AbRunner::new(board.clone(), time_manager.clone());
+        runner.set_multi_pv(3);
+        let updates = Arc::new(Mutex::new(vec![]));
+        let observer_updates = updates.clone();
+        let observer =
+            move |update: &SearchUpdate| observer_updates.lock().unwrap().push(update.clone());
+        time_manager.initiate(&board, &[TimeManagementInfo::MaxDepth(2)]);
+        let (make_move, _, _, _, _) = runner.search::<Run>(Arc::new(Mutex::new(observer)));
+        time_manager.clear();
+
+        let mate = "a1a8".parse().unwrap();
+        assert_eq!(make_move, mate);
+        let updates = updates.lock().unwrap();
+        let last = &updates[updates.len() - 3..];
+        assert_eq!(last[0].pv[0], mate);
+        assert_eq!(last[0].score, Score::Mate(1));
+        assert!(last
+            .iter()
+            .enumerate()
+            .all(|(index, line)| line.multi_pv == index + 1));
+    });
 }
@@ -1,8 +1,10 @@
 use crate::bm::bm_util::eval::Evaluation;
+use crate::bm::bm_util::t_table::Bounds;
 use cozy_chess::{Board, Move};
-use std::fmt::{Display, Write as FmtWrite};
+use std::fmt::Display;
 use std::fs::OpenOptions;
 use std::io::Write;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 #[derive(Debug, Clone)]
@@ -125,84 +127,96 @@ fn extend(string: &mut String, len: usize) {
     }
 }
 
-pub trait GuiInfo {
-    fn new() -> Self;
-
-    fn print_info(
-        &self,
-        sel_depth: u32,
-        depth: u32,
-        eval: Evaluation,
-        wld: Option<(i16, i16, i16)>,
-        elapsed: Duration,
-        node_cnt: u64,
-        pv: &[Move],
-    );
+/// Side to move relative score of a search line
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Score {
+    /// Centipawns
+    Cp(i16),
+    /// Moves until mate, negative if the side to move is getting mated
+    Mate(i16),
 }
 
-#[derive(Debug, Clone)]
-pub struct NoInfo;
-
-impl GuiInfo for NoInfo {
-    fn new() -> Self {
-        Self {}
+impl From<Evaluation> for Score {
+    fn from(eval: Evaluation) -> Self {
+        match eval.mate_in() {
+            Some(mate_in) => Score::Mate(mate_in),
+            None => Score::Cp(eval.raw()),
+        }
     }
+}
 
-    fn print_info(
-        &self,
-        _: u32,
-        _: u32,
-        _: Evaluation,
-        _: Option<(i16, i16, i16)>,
-        _: Duration,
-        _: u64,
-        _: &[Move],
-    ) {
+impl Display for Score {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Score::Cp(cp) => write!(f, "cp {}", cp),
+            Score::Mate(mate_in) => write!(f, "mate {}", mate_in),
+        }
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct UciInfo;
+/// Result of searching a MultiPV line, sent when a depth completes or the aspiration window fails
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchUpdate {
+    pub depth: u32,
+    pub sel_depth: u32,
+    /// 1-based index of the MultiPV line
+    pub multi_pv: usize,
+    pub score: Score,
+    /// Score is only a lower or upper bound if the aspiration window failed
+    pub bound: Bounds,
+    /// Win, draw and loss chances in permille, only sent with `UCI_ShowWDL`
+    pub wdl: Option<(u16, u16, u16)>,
+    pub time: Duration,
+    pub nodes: u64,
+    /// Permille of the transposition table used by the current search
+    pub hashfull: u32,
+    pub tb_hits: u64,
+    /// Principal variation in UCI notation
+    pub pv: Vec<Move>,
+}
 
-impl GuiInfo for UciInfo {
-    fn new() -> Self {
-        Self {}
-    }
+/// Final result of a search, moves are in UCI notation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResult {
+    /// None if the side to move is checkmated or stalemated
+    pub best_move: Option<Move>,
+    /// Expected reply to the best move
+    pub ponder_move: Option<Move>,
+    pub score: Score,
+    pub depth: u32,
+    /// Nodes searched by all threads
+    pub nodes: u64,
+}
 
-    fn print_info(
-        &self,
-        seldepth: u32,
-        depth: u32,
-        eval: Evaluation,
-        wld: Option<(i16, i16, i16)>,
-        elapsed: Duration,
-        node_cnt: u64,
-        pv: &[Move],
-    ) {
-        let eval_str = if eval.is_mate() {
-            format!("mate {}", eval.mate_in().unwrap())
-        } else {
-            format!("cp {}", eval.raw())
-        };
-        let nps = (node_cnt as u128 * 1000) / elapsed.as_millis().max(1);
-
-        let wdl = match wld {
-            Some(wld) => format!("wdl {} {} {} ", wld.0, wld.2, wld.1),
-            None => "".to_string(),
-        };
-        let mut output = format!(
-            "info depth {} seldepth {} score {} {}time {} nodes {} nps {} pv",
-            depth,
-            seldepth,
-            eval_str,
-            wdl,
-            elapsed.as_millis(),
-            node_cnt,
-            nps
-        );
-        for make_move in pv {
-            write!(&mut output, " {}", make_move).unwrap();
-        }
-        println!("{}", output);
+/// Receives the progress and result of a search
+/// - Called from the search thread, only the main thread reports progress
+/// - Protocol output, logging and library users are implemented as observers
+pub trait SearchObserver: Send {
+    fn update(&mut self, update: &SearchUpdate);
+
+    /// Root move being searched and its 1-based position in the move order
+    /// - Only sent once the search has run for a few seconds
+    fn current_move(&mut self, _depth: u32, _make_move: Move, _move_number: usize) {}
+
+    /// Sent about once a second, independently of completed iterations
+    fn progress(&mut self, _elapsed: Duration, _nodes: u64, _hashfull: u32) {}
+
+    /// Sent once the search is done and allowed to report its result
+    fn complete(&mut self, _result: &SearchResult) {}
+}
+
+impl<F: FnMut(&SearchUpdate) + Send> SearchObserver for F {
+    fn update(&mut self, update: &SearchUpdate) {
+        self(update)
     }
 }
+
+/// Observer shared between the caller and the main search thread
+pub type SharedObserver = Arc<Mutex<dyn SearchObserver>>;
+
+#[derive(Debug, Clone, Default)]
+pub struct NoObserver;
+
+impl SearchObserver for NoObserver {
+    fn update(&mut self, _: &SearchUpdate) {}
+}
@@ -1,3 +1,4 @@
 pub mod ab_runner;
 pub mod config;
+pub mod skill;
 pub mod time;
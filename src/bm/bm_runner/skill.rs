@@ -0,0 +1,151 @@
+//! Strength limiting for `Skill Level`
+//!
+//! Weaker levels search fewer nodes and pick among the best MultiPV lines at random,
+//! preferring moves that lose less. The Elo scale is measured with the `calibrate` command,
+//! `UCI_LimitStrength` and `UCI_Elo` aren't offered until [LEVEL_ELO] has been measured.
+
+use cozy_chess::Move;
+
+use crate::bm::bm_util::eval::Evaluation;
+
+pub const MAX_LEVEL: u8 = 20;
+
+/// Elo of skill levels, must be increasing
+/// - Placeholder estimates that haven't been measured against a reference engine
+/// - Measure with `calibrate <games> <engine> <elo>` against a UCI engine supporting `UCI_Elo`,
+///   which prints this table along with the games, time control and reference it was measured with
+pub const LEVEL_ELO: &[(u8, i64)] = &[
+    (0, 1100),
+    (4, 1500),
+    (8, 1900),
+    (12, 2250),
+    (16, 2600),
+    (20, 3000),
+];
+
+pub const MIN_ELO: i64 = LEVEL_ELO[0].1;
+pub const MAX_ELO: i64 = LEVEL_ELO[LEVEL_ELO.len() - 1].1;
+
+/// Number of lines searched to choose a weaker move from, only the requested lines are reported
+const SKILL_MULTI_PV: usize = 4;
+
+/// xorshift64* generator, move choice doesn't need a better source of randomness
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    /// Seeds the generator from the system clock
+    pub fn from_time() -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64);
+        Self::new(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Skill {
+    /// Fractional skill level, [MAX_LEVEL] is full strength
+    level: f32,
+}
+
+impl Skill {
+    pub fn from_level(level: u8) -> Self {
+        Self {
+            level: level.min(MAX_LEVEL) as f32,
+        }
+    }
+
+    /// Interpolates the skill level between the [LEVEL_ELO] anchors
+    pub fn from_elo(elo: i64) -> Self {
+        let elo = elo.clamp(MIN_ELO, MAX_ELO);
+        let upper = LEVEL_ELO
+            .iter()
+            .position(|&(_, anchor)| anchor >= elo)
+            .unwrap()
+            .max(1);
+        let (low_level, low_elo) = LEVEL_ELO[upper - 1];
+        let (high_level, high_elo) = LEVEL_ELO[upper];
+        let progress = (elo - low_elo) as f32 / (high_elo - low_elo) as f32;
+        Self {
+            level: low_level as f32 + progress * (high_level - low_level) as f32,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.level < MAX_LEVEL as f32
+    }
+
+    /// Nodes each search may use, doubling every two levels
+    pub fn node_limit(&self) -> u64 {
+        (1000.0 * 2_f32.powf(self.level * 0.5)) as u64
+    }
+
+    /// Lines that have to be searched for the move choice
+    pub fn multi_pv(&self, multi_pv: usize) -> usize {
+        match self.enabled() {
+            true => multi_pv.max(SKILL_MULTI_PV),
+            false => multi_pv,
+        }
+    }
+
+    /// Chooses a root move among MultiPV lines, lower levels are more likely to play worse moves
+    pub fn pick(&self, lines: &[(Move, Evaluation)], rng: &mut Rng) -> Option<(Move, Evaluation)> {
+        let &(top_move, top_score) = lines.iter().max_by_key(|(_, score)| *score)?;
+        if top_score.is_mate() {
+            return Some((top_move, top_score));
+        }
+        let lowest = lines.iter().map(|(_, score)| score.raw()).min()?;
+        let weakness = (120.0 - 2.0 * self.level) as i32;
+        let delta = (top_score.raw() as i32 - lowest as i32).min(100);
+        lines
+            .iter()
+            .filter(|(_, score)| !score.is_mate())
+            .max_by_key(|(_, score)| {
+                let loss = top_score.raw() as i32 - score.raw() as i32;
+                let noise = (rng.next_u64() % weakness as u64) as i32;
+                score.raw() as i32 + (weakness * loss + delta * noise) / 128
+            })
+            .copied()
+    }
+}
+
+#[test]
+fn skill_levels() {
+    assert!(!Skill::from_level(MAX_LEVEL).enabled());
+    assert!(!Skill::from_elo(MAX_ELO).enabled());
+    assert_eq!(Skill::from_elo(0), Skill::from_level(0));
+    assert_eq!(
+        Skill::from_elo(LEVEL_ELO[2].1),
+        Skill::from_level(LEVEL_ELO[2].0)
+    );
+    assert!(Skill::from_level(5).node_limit() < Skill::from_level(6).node_limit());
+
+    let lines = [
+        ("e2e4".parse().unwrap(), Evaluation::new(20)),
+        ("d2d4".parse().unwrap(), Evaluation::new(20)),
+        ("g1h3".parse().unwrap(), Evaluation::new(-300)),
+    ];
+    let mut rng = Rng::new(1);
+    let mut picks = |level: u8| {
+        (0..256)
+            .map(|_| Skill::from_level(level).pick(&lines, &mut rng).unwrap().0)
+            .collect::<Vec<Move>>()
+    };
+    // Low levels sometimes blunder, high levels only vary between equal moves
+    assert!(picks(0).contains(&lines[2].0));
+    let strong = picks(19);
+    assert!(strong.contains(&lines[0].0) && strong.contains(&lines[1].0));
+    assert!(!strong.contains(&lines[2].0));
+}
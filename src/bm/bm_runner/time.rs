@@ -18,6 +18,12 @@ const NODES_DEFAULT: u64 = u64::MAX;
 
 const MOVES_TO_GO_DEFAULT: Option<u32> = None;
 
+const MOVE_OVERHEAD_DEFAULT: u32 = 10;
+/// Every search is allowed at least this many milliseconds
+const MIN_THINK_TIME: u32 = 5;
+/// Below this many milliseconds on the clock, moves are played much faster
+const EMERGENCY_TIME: u32 = 1000;
+
 #[derive(Debug, Copy, Clone)]
 pub enum TimeManagementInfo {
     WTime(Duration),
@@ -28,7 +34,12 @@ pub enum TimeManagementInfo {
     MaxNodes(u64),
     MovesToGo(u32),
     MoveTime(Duration),
-    Unknown,
+    /// Search the predicted position without time limits until `ponderhit` or `stop`
+    Ponder,
+    /// Search without limits until `stop`
+    Infinite,
+    /// Stop as soon as a mate in the given number of moves or less is found
+    Mate(u32),
 }
 
 #[derive(Debug)]
@@ -47,9 +58,27 @@ pub struct TimeManager {
     infinite: AtomicBool,
     abort_now: AtomicBool,
     no_manage: AtomicBool,
+    /// Search may only report after `stop`
+    until_stop: AtomicBool,
+    /// Set once the main thread is done to stop the remaining threads
+    finished: AtomicBool,
+
+    pondering: AtomicBool,
+    /// Time is measured from this instant instead of search start after a ponder hit
+    ponder_hit: Mutex<Option<Instant>>,
 
     max_depth: AtomicU32,
     max_nodes: AtomicU64,
+    /// Mate distance in moves to stop at, 0 if disabled
+    max_mate: AtomicU32,
+    /// Milliseconds reserved for communication latency on every move
+    move_overhead: AtomicU32,
+    /// Nodes searched per millisecond of budget, 0 if time is measured by the clock
+    nodes_time: AtomicU32,
+    /// Remaining nodes on the engine's clock with `nodes_time`, set by the first clock of a game
+    available_nodes: Mutex<Option<u64>>,
+    /// Nodes credited to the budget after the current search, None if it isn't clock based
+    nodes_inc: Mutex<Option<u64>>,
 }
 
 impl TimeManager {
@@ -66,8 +95,17 @@ impl TimeManager {
             abort_now: AtomicBool::new(false),
             infinite: AtomicBool::new(true),
             no_manage: AtomicBool::new(true),
+            until_stop: AtomicBool::new(false),
+            finished: AtomicBool::new(false),
+            pondering: AtomicBool::new(false),
+            ponder_hit: Mutex::new(None),
             max_depth: AtomicU32::new(DEPTH_DEFAULT),
             max_nodes: AtomicU64::new(NODES_DEFAULT),
+            max_mate: AtomicU32::new(0),
+            move_overhead: AtomicU32::new(MOVE_OVERHEAD_DEFAULT),
+            nodes_time: AtomicU32::new(0),
+            available_nodes: Mutex::new(None),
+            nodes_inc: Mutex::new(None),
         }
     }
 }
@@ -109,6 +147,7 @@ impl TimeManager {
 
     pub fn initiate(&self, board: &Board, info: &[TimeManagementInfo]) {
         self.abort_now.store(false, Ordering::SeqCst);
+        self.finished.store(false, Ordering::SeqCst);
         *self.board.lock().unwrap() = board.clone();
 
         let mut move_cnt = 0;
@@ -127,6 +166,9 @@ impl TimeManager {
         let mut max_nodes = NODES_DEFAULT;
         let mut moves_to_go = MOVES_TO_GO_DEFAULT;
         let mut move_time = None;
+        let mut ponder = false;
+        let mut until_stop = false;
+        let mut max_mate = 0;
 
         for info in info {
             match info {
@@ -157,10 +199,23 @@ impl TimeManager {
                     move_time = Some(*time);
                     infinite = false;
                 }
-                _ => {}
+                TimeManagementInfo::Ponder => {
+                    ponder = true;
+                }
+                TimeManagementInfo::Infinite => {
+                    until_stop = true;
+                }
+                TimeManagementInfo::Mate(moves) => {
+                    max_mate = *moves;
+                }
             }
         }
+        let infinite = infinite || until_stop;
         self.infinite.store(infinite, Ordering::SeqCst);
+        self.pondering.store(ponder, Ordering::SeqCst);
+        self.until_stop.store(until_stop, Ordering::SeqCst);
+        self.max_mate.store(max_mate, Ordering::SeqCst);
+        *self.ponder_hit.lock().unwrap() = None;
         self.max_depth.store(max_depth, Ordering::SeqCst);
         self.max_nodes.store(max_nodes, Ordering::SeqCst);
 
@@ -172,17 +227,45 @@ impl TimeManager {
         let no_manage = infinite || move_time.is_some();
         self.no_manage.store(no_manage, Ordering::SeqCst);
 
+        // The clock of the GUI only initializes the node budget, which is then kept internally
+        let nodes_time = self.nodes_time.load(Ordering::SeqCst) as u64;
+        let (time, inc) = match nodes_time != 0 && !no_manage {
+            true => {
+                let mut available_nodes = self.available_nodes.lock().unwrap();
+                let budget = *available_nodes.get_or_insert(time.as_millis() as u64 * nodes_time);
+                *self.nodes_inc.lock().unwrap() = Some(inc.as_millis() as u64 * nodes_time);
+                (Duration::from_millis(budget / nodes_time), inc)
+            }
+            false => {
+                *self.nodes_inc.lock().unwrap() = None;
+                (time, inc)
+            }
+        };
+
+        let move_overhead = self.move_overhead.load(Ordering::SeqCst);
         if let Some(move_time) = move_time {
-            let move_time = move_time.as_millis() as u32;
+            let move_time = (move_time.as_millis() as u32)
+                .saturating_sub(move_overhead)
+                .max(MIN_THINK_TIME);
             self.target_duration.store(move_time, Ordering::SeqCst);
             self.max_duration.store(move_time, Ordering::SeqCst);
         } else if move_cnt == 0 {
             self.target_duration.store(0, Ordering::SeqCst);
         } else {
-            let max_time = time.as_millis() as u32 * 4 / 5;
+            let time = (time.as_millis() as u32).saturating_sub(move_overhead);
+            let mut inc = inc.as_millis() as u32;
+            // The clock may run out before the increment arrives
+            let max_time = match time < EMERGENCY_TIME {
+                true => {
+                    inc /= 2;
+                    time / 4
+                }
+                false => time * 4 / 5,
+            }
+            .max(MIN_THINK_TIME);
             let expected_moves = moves_to_go.unwrap_or(EXPECTED_MOVES) + 1;
             let default = if move_cnt > 1 {
-                (inc.as_millis() as u32 + time.as_millis() as u32 / expected_moves).min(max_time)
+                (inc + time / expected_moves).min(max_time)
             } else {
                 0
             };
@@ -192,27 +275,113 @@ impl TimeManager {
         };
     }
 
+    /// Sets the time subtracted from every budget to account for latency
+    pub fn set_move_overhead(&self, move_overhead: Duration) {
+        self.move_overhead
+            .store(move_overhead.as_millis() as u32, Ordering::SeqCst);
+    }
+
+    /// Measures time in searched nodes instead of the clock, 0 restores the clock
+    /// - Every millisecond of `movetime` and of the first clock of a game is worth `nodes_time` nodes
+    /// - Later clocks are ignored, the budget is debited by the nodes searched by all threads
+    pub fn set_nodes_time(&self, nodes_time: u32) {
+        self.nodes_time.store(nodes_time, Ordering::SeqCst);
+        self.new_game();
+    }
+
+    /// Debits the nodes of a finished search from the node budget and credits the increment
+    pub fn nodes_searched(&self, nodes: u64) {
+        if let Some(inc) = self.nodes_inc.lock().unwrap().take() {
+            if let Some(available_nodes) = self.available_nodes.lock().unwrap().as_mut() {
+                *available_nodes = (*available_nodes + inc).saturating_sub(nodes);
+            }
+        }
+    }
+
+    /// Forgets the node budget of the previous game
+    pub fn new_game(&self) {
+        *self.available_nodes.lock().unwrap() = None;
+        *self.nodes_inc.lock().unwrap() = None;
+    }
+
+    /// Lowers the node limit of the current search
+    pub fn limit_nodes(&self, nodes: u64) {
+        self.max_nodes.fetch_min(nodes, Ordering::SeqCst);
+    }
+
     pub fn abort_now(&self) {
         self.abort_now.store(true, Ordering::SeqCst);
     }
 
-    pub fn abort_search(&self, start: Instant, nodes: u64) -> bool {
-        if self.abort_now.load(Ordering::SeqCst) {
+    /// Converts a running ponder search into a normally time managed search
+    /// - Time spent pondering isn't counted against the time budget
+    pub fn ponder_hit(&self) {
+        *self.ponder_hit.lock().unwrap() = Some(Instant::now());
+        self.pondering.store(false, Ordering::SeqCst);
+    }
+
+    /// Blocks until the search is allowed to report its result
+    /// - A ponder search may only report after `ponderhit` or `stop`
+    /// - An infinite search may only report after `stop`
+    pub fn wait_for_release(&self) {
+        while (self.pondering.load(Ordering::SeqCst) || self.until_stop.load(Ordering::SeqCst))
+            && !self.abort_now.load(Ordering::SeqCst)
+        {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// Signals the remaining threads to stop once the main thread has returned
+    pub fn finish(&self) {
+        self.finished.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns true if the evaluation is a mate within the `go mate` limit
+    pub fn mate_found(&self, eval: Evaluation) -> bool {
+        let max_mate = self.max_mate.load(Ordering::SeqCst);
+        eval.mate_in()
+            .is_some_and(|mate_in| max_mate != 0 && mate_in > 0 && mate_in as u32 <= max_mate)
+    }
+
+    /// Milliseconds elapsed since start or the latest ponder hit
+    /// - With `nodestime` elapsed time is derived from the nodes searched by all threads
+    fn elapsed(&self, start: Instant, total_nodes: u64) -> u32 {
+        let nodes_time = self.nodes_time.load(Ordering::SeqCst);
+        if nodes_time != 0 {
+            return (total_nodes / nodes_time as u64) as u32;
+        }
+        let ponder_hit = *self.ponder_hit.lock().unwrap();
+        let start = ponder_hit.map_or(start, |ponder_hit| ponder_hit.max(start));
+        start.elapsed().as_millis() as u32
+    }
+
+    /// Time limits don't apply to infinite and ponder searches
+    fn time_limited(&self) -> bool {
+        !self.infinite.load(Ordering::SeqCst) && !self.pondering.load(Ordering::SeqCst)
+    }
+
+    pub fn abort_search(&self, start: Instant, nodes: u64, total_nodes: u64) -> bool {
+        if self.abort_now.load(Ordering::SeqCst) || self.finished.load(Ordering::SeqCst) {
             true
         } else {
-            (self.max_duration.load(Ordering::SeqCst) < start.elapsed().as_millis() as u32
-                && !self.infinite.load(Ordering::SeqCst))
+            (self.time_limited()
+                && self.max_duration.load(Ordering::SeqCst) < self.elapsed(start, total_nodes))
                 || self.max_nodes.load(Ordering::SeqCst) <= nodes
         }
     }
 
-    pub fn abort_deepening(&self, start: Instant, depth: u32, nodes: u64) -> bool {
-        if self.abort_now.load(Ordering::SeqCst) {
+    pub fn abort_deepening(
+        &self,
+        start: Instant,
+        depth: u32,
+        nodes: u64,
+        total_nodes: u64,
+    ) -> bool {
+        if self.abort_now.load(Ordering::SeqCst) || self.finished.load(Ordering::SeqCst) {
             true
         } else {
-            let abort_std = self.target_duration.load(Ordering::SeqCst)
-                < start.elapsed().as_millis() as u32
-                && !self.infinite.load(Ordering::SeqCst);
+            let abort_std = self.time_limited()
+                && self.target_duration.load(Ordering::SeqCst) < self.elapsed(start, total_nodes);
             abort_std
                 || self.max_depth.load(Ordering::SeqCst) < depth
                 || self.max_nodes.load(Ordering::SeqCst) <= nodes
@@ -221,6 +390,10 @@ impl TimeManager {
 
     pub fn clear(&self) {
         *self.prev_move.lock().unwrap() = None;
+        *self.ponder_hit.lock().unwrap() = None;
+        self.pondering.store(false, Ordering::SeqCst);
+        self.until_stop.store(false, Ordering::SeqCst);
+        self.finished.store(false, Ordering::SeqCst);
         self.abort_now.store(false, Ordering::SeqCst);
         self.no_manage.store(false, Ordering::SeqCst);
         let expected_moves = self.expected_moves.load(Ordering::SeqCst);
@@ -229,3 +402,56 @@ impl TimeManager {
         self.move_stability.store(0, Ordering::Relaxed);
     }
 }
+
+#[test]
+fn move_overhead_budgets() {
+    use TimeManagementInfo::*;
+
+    let time_manager = TimeManager::new();
+    let board = Board::default();
+    let budget = |info: &[TimeManagementInfo]| {
+        time_manager.initiate(&board, info);
+        time_manager.max_duration.load(Ordering::SeqCst)
+    };
+    time_manager.set_move_overhead(Duration::from_millis(100));
+    assert_eq!(budget(&[MoveTime(Duration::from_millis(1000))]), 900);
+    assert_eq!(
+        budget(&[MoveTime(Duration::from_millis(50))]),
+        MIN_THINK_TIME
+    );
+    assert_eq!(budget(&[WTime(Duration::from_millis(10100))]), 8000);
+    // Emergency mode keeps most of a low clock in reserve
+    assert_eq!(budget(&[WTime(Duration::from_millis(900))]), 200);
+    assert_eq!(budget(&[WTime(Duration::from_millis(0))]), MIN_THINK_TIME);
+}
+
+#[test]
+fn nodes_time_budget() {
+    let time_manager = TimeManager::new();
+    time_manager.set_move_overhead(Duration::ZERO);
+    time_manager.set_nodes_time(100);
+    time_manager.initiate(
+        &Board::default(),
+        &[TimeManagementInfo::MoveTime(Duration::from_millis(1000))],
+    );
+    let start = Instant::now();
+    assert!(!time_manager.abort_search(start, 99_000, 99_000));
+    assert!(time_manager.abort_search(start, 50_000, 100_100));
+
+    let budget = |wtime: u64| {
+        time_manager.initiate(
+            &Board::default(),
+            &[
+                TimeManagementInfo::WTime(Duration::from_millis(wtime)),
+                TimeManagementInfo::WInc(Duration::from_millis(10)),
+            ],
+        );
+        time_manager.max_duration.load(Ordering::SeqCst)
+    };
+    // 2000 ms are worth 200000 nodes, after 101000 nodes and the increment 1000 ms are left
+    assert_eq!(budget(2000), 1600);
+    time_manager.nodes_searched(101_000);
+    assert_eq!(budget(60_000), 800);
+    time_manager.new_game();
+    assert_eq!(budget(60_000), 48_000);
+}
@@ -123,7 +123,7 @@ pub fn search<Search: SearchType>(
     thread.update_sel_depth(ply);
     if ply != 0 && pos.forced_draw(ply) {
         thread.increment_nodes();
-        return Evaluation::new(0);
+        return shared_context.draw_score(thread.stm, pos.board().side_to_move());
     }
 
     /*
@@ -180,6 +180,18 @@ pub fn search<Search: SearchType>(
         thread.tt_misses += 1;
     }
 
+    /*
+    Tablebase Probing:
+    Positions covered by the tablebase have a known result,
+    so there is no need to search them
+    */
+    if ply != 0 && skip_move.is_none() {
+        if let Some(wdl) = shared_context.probe_wdl(pos.board()) {
+            let draw = shared_context.draw_score(thread.stm, pos.board().side_to_move());
+            return wdl.to_eval(ply, draw);
+        }
+    }
+
     let in_check = !pos.board().checkers().is_empty();
 
     let tt_eval = tt_entry.and_then(|entry| entry.eval);
@@ -187,7 +199,7 @@ pub fn search<Search: SearchType>(
         Some(_) => thread.ss[ply as usize].eval,
         None => tt_eval.unwrap_or_else(|| pos.get_eval()),
     };
-    let aggr = pos.aggression(thread.stm, thread.eval);
+    let aggr = pos.aggression(thread.stm, thread.eval, shared_context.get_aggression());
     let corr = thread.history.get_correction(pos);
     let eval = raw_eval + aggr + corr;
 
@@ -316,6 +328,13 @@ pub fn search<Search: SearchType>(
         if Some(make_move) == skip_move {
             continue;
         }
+        if ply == 0 && (thread.is_excluded(make_move) || !shared_context.is_search_move(make_move))
+        {
+            continue;
+        }
+        if ply == 0 {
+            shared_context.report_cur_move(pos.board(), depth, make_move, moves_seen + 1);
+        }
 
         move_exists = true;
         let is_capture = pos.is_capture(make_move);
@@ -627,13 +646,19 @@ pub fn search<Search: SearchType>(
     }
     if !move_exists {
         return match pos.board().checkers().is_empty() {
-            true => Evaluation::new(0),
+            true => shared_context.draw_score(thread.stm, pos.board().side_to_move()),
             false => Evaluation::new_checkmate(-1),
         };
     }
     let highest_score = highest_score.unwrap();
 
-    if skip_move.is_none() && !thread.abort {
+    /*
+    Secondary MultiPV lines and searches restricted by searchmoves
+    don't search all root moves, so their results can't be stored
+    */
+    let partial_root =
+        ply == 0 && (!thread.excluded_moves.is_empty() || shared_context.restricted_root());
+    if skip_move.is_none() && !partial_root && !thread.abort {
         let entry_type = match () {
             _ if highest_score <= initial_alpha => Bounds::UpperBound,
             _ if highest_score >= beta => Bounds::LowerBound,
@@ -689,7 +714,9 @@ pub fn q_search(
     thread.update_sel_depth(ply);
     let correction = thread.history.get_correction(pos);
     if ply >= MAX_PLY {
-        return pos.get_eval() + pos.aggression(thread.stm, thread.eval) + correction;
+        return pos.get_eval()
+            + pos.aggression(thread.stm, thread.eval, shared_context.get_aggression())
+            + correction;
     }
 
     let mut best_move = None;
@@ -717,7 +744,8 @@ pub fn q_search(
 
     let tt_eval = tt_entry.and_then(|entry| entry.eval);
     let raw_eval = tt_eval.unwrap_or_else(|| pos.get_eval());
-    let stand_pat = raw_eval + pos.aggression(thread.stm, thread.eval);
+    let stand_pat =
+        raw_eval + pos.aggression(thread.stm, thread.eval, shared_context.get_aggression());
     /*
     If not in check, we have a stand pat score which is the static eval of the current position.
     This is done as captures aren't necessarily the best moves.
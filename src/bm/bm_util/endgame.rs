@@ -0,0 +1,135 @@
+//! Endgame knowledge for trivial endings the network doesn't evaluate reliably
+//!
+//! All recognizers require one side to have a bare king.
+
+use cozy_chess::{BitBoard, Board, Color, File, Piece, Rank, Square};
+
+use crate::bm::tablebase::{self, Wdl};
+
+use super::eval::Evaluation;
+
+/// Endings that are won with correct technique, scored below tablebase wins
+const KNOWN_WIN: i16 = 10000;
+
+/// Generates the KPK bitbase ahead of time so it isn't generated mid search
+pub fn init() {
+    tablebase::init_kpk();
+}
+
+fn distance(a: Square, b: Square) -> i16 {
+    let files = (a.file() as i16 - b.file() as i16).abs();
+    let ranks = (a.rank() as i16 - b.rank() as i16).abs();
+    files.max(ranks)
+}
+
+/// Returns the side playing against a bare king
+fn strong_side(board: &Board) -> Option<Color> {
+    let white = board.colors(Color::White).len();
+    let black = board.colors(Color::Black).len();
+    match (white, black) {
+        (2.., 1) => Some(Color::White),
+        (1, 2..) => Some(Color::Black),
+        _ => None,
+    }
+}
+
+/// Exact score of KPK from the bitbase, pushing the pawn when winning
+fn kpk(board: &Board, strong: Color) -> Option<i16> {
+    let wdl = tablebase::probe_kpk(board)?;
+    let pawn = board.colored_pieces(strong, Piece::Pawn).next_square()?;
+    let rank = pawn.rank().relative_to(strong) as i16;
+    Some(match wdl {
+        Wdl::Draw => 0,
+        _ => KNOWN_WIN + rank * 32,
+    })
+}
+
+/// Drives the defending king into a corner the bishop controls
+fn kbnk(board: &Board, strong: Color) -> i16 {
+    let weak_king = board.king(!strong);
+    let bishops = board.colored_pieces(strong, Piece::Bishop);
+    let corners = match BitBoard::DARK_SQUARES.is_superset(bishops) {
+        true => [Square::A1, Square::H8],
+        false => [Square::A8, Square::H1],
+    };
+    let corner_distance = corners
+        .into_iter()
+        .map(|corner| distance(weak_king, corner))
+        .min()
+        .unwrap();
+    let king_distance = distance(weak_king, board.king(strong));
+    KNOWN_WIN + (7 - corner_distance) * 64 + (7 - king_distance) * 16
+}
+
+/// Bishop and rook pawns can't win if the defending king reaches the corner
+/// and the bishop doesn't control the promotion square
+fn wrong_bishop(board: &Board, strong: Color) -> bool {
+    let pawns = board.colored_pieces(strong, Piece::Pawn);
+    let bishops = board.colored_pieces(strong, Piece::Bishop);
+    let file = [File::A, File::H]
+        .into_iter()
+        .find(|file| file.bitboard().is_superset(pawns));
+    let Some(file) = file else {
+        return false;
+    };
+    let promotion = Square::new(file, Rank::Eighth.relative_to(strong));
+    let dark_bishop = BitBoard::DARK_SQUARES.is_superset(bishops);
+    let dark_promotion = BitBoard::DARK_SQUARES.has(promotion);
+    dark_bishop != dark_promotion && distance(board.king(!strong), promotion) <= 1
+}
+
+/// Returns the side to move relative score of recognized endgames
+/// - KPK is scored exactly using the bitbase
+/// - KBNK is a known win
+/// - KNNK and bishop with rook pawns of the wrong color are draws
+pub fn evaluate(board: &Board) -> Option<Evaluation> {
+    let strong = strong_side(board)?;
+    let count = |piece: Piece| board.colored_pieces(strong, piece).len();
+    let (pawns, knights, bishops) = (
+        count(Piece::Pawn),
+        count(Piece::Knight),
+        count(Piece::Bishop),
+    );
+    if count(Piece::Rook) + count(Piece::Queen) != 0 {
+        return None;
+    }
+    let score = match (pawns, knights, bishops) {
+        (1, 0, 0) => kpk(board, strong)?,
+        (0, 1, 1) => kbnk(board, strong),
+        (0, 2, 0) => 0,
+        (1.., 0, 1) if wrong_bishop(board, strong) => 0,
+        _ => return None,
+    };
+    Some(Evaluation::new(match board.side_to_move() == strong {
+        true => score,
+        false => -score,
+    }))
+}
+
+/// Returns true for endgames that are drawn with correct play
+pub fn is_draw(board: &Board) -> bool {
+    board.occupied().len() == 3 && tablebase::probe_kpk(board) == Some(Wdl::Draw)
+}
+
+#[test]
+fn recognized_endgames() {
+    let eval = |fen: &str| evaluate(&fen.parse::<Board>().unwrap()).map(|eval| eval.raw());
+
+    // KPK draw and win, from both sides
+    assert_eq!(eval("8/8/8/8/8/4k3/4P3/4K3 w - - 0 1"), Some(0));
+    assert!(eval("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1").unwrap() > KNOWN_WIN);
+    assert!(eval("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1").unwrap() < -KNOWN_WIN);
+    assert!(is_draw(&"4k3/4p3/4K3/8/8/8/8/8 b - - 0 1".parse().unwrap()));
+
+    // KBNK prefers the defending king in the bishop's corner
+    let right_corner = eval("7k/8/5K2/8/8/8/8/2B1N3 w - - 0 1").unwrap();
+    let wrong_corner = eval("k7/8/2K5/8/8/8/8/2B1N3 w - - 0 1").unwrap();
+    assert!(right_corner > wrong_corner && wrong_corner > KNOWN_WIN);
+
+    assert_eq!(eval("8/8/8/4k3/8/8/8/2N1NK2 b - - 0 1"), Some(0));
+
+    // Light squared bishop can't control h8
+    assert_eq!(eval("7k/8/8/7P/8/8/7P/4KB2 w - - 0 1"), Some(0));
+    assert_eq!(eval("7k/8/8/7P/8/8/7P/4K1B1 w - - 0 1"), None);
+    assert_eq!(eval("8/8/8/3k4/8/8/7P/4KB2 w - - 0 1"), None);
+}
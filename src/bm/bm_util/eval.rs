@@ -1,6 +1,7 @@
 const CHECKMATE: i16 = 128;
 const CHECKMATE_EVAL: i16 = i16::MAX - 1024;
 const MAX_EVAL: i16 = CHECKMATE_EVAL - CHECKMATE;
+const TB_WIN_EVAL: i16 = MAX_EVAL - CHECKMATE;
 
 pub enum Depth {
     Next,
@@ -26,6 +27,13 @@ impl Evaluation {
         }
     }
 
+    /// Tablebase wins rank below mates and above all regular evaluations
+    pub fn new_tb_win(ply: u32) -> Self {
+        Self {
+            score: TB_WIN_EVAL - ply as i16,
+        }
+    }
+
     pub const fn is_mate(&self) -> bool {
         self.score.saturating_abs() > MAX_EVAL
     }
@@ -2,7 +2,25 @@ use cozy_chess::{BitBoard, Board, Color, GameStatus, Move, Piece};
 
 use crate::bm::nnue::Nnue;
 
-use super::{eval::Evaluation, frc, threats::threats, zobrist::Zobrist};
+use super::{endgame, eval::Evaluation, frc, threats::threats, zobrist::Zobrist};
+
+/// Shifts search scores in favor of the side that is better at the root, avoiding simplification
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Aggression {
+    /// Percentage of the root evaluation added per non-pawn piece
+    pub scale: i16,
+    /// Root evaluations are clamped to this many centipawns
+    pub limit: i16,
+}
+
+impl Default for Aggression {
+    fn default() -> Self {
+        Self {
+            scale: 2,
+            limit: 200,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Position {
@@ -55,6 +73,13 @@ impl Position {
         self.last_eval = 0;
     }
 
+    /// Replaces the neural network used for evaluation
+    /// Forces recalculation of NNUE accumulators
+    pub fn set_evaluator(&mut self, evaluator: Nnue) {
+        self.evaluator = evaluator;
+        self.reset();
+    }
+
     /// Forces recalculation of NNUE accumulators
     pub fn reset(&mut self) {
         self.evaluator
@@ -68,8 +93,10 @@ impl Position {
     /// after search root, it's considered a three fold repetition
     ///
     /// Returns true if [insufficient material](Self::insufficient_material)
+    /// or a drawn KPK endgame
     pub fn forced_draw(&self, ply: u32) -> bool {
         if self.insufficient_material()
+            || endgame::is_draw(&self.current)
             || (self.current.halfmove_clock() >= 100
                 && (self.current.checkers().is_empty() || self.current.status() != GameStatus::Won))
         {
@@ -217,20 +244,25 @@ impl Position {
     /// Returns aggression value
     /// - Value may vary depending on position and root evaluation
     /// - Avoid storing, instead recalculate for a given position
-    pub fn aggression(&self, stm: Color, root_eval: Evaluation) -> i16 {
+    pub fn aggression(&self, stm: Color, root_eval: Evaluation, aggression: Aggression) -> i16 {
         let piece_cnt = self.board().occupied().len() - self.board().pieces(Piece::Pawn).len();
-        let scale = 2 * piece_cnt as i16;
+        let scale = aggression.scale as i32 * piece_cnt as i32;
 
-        let clamped_eval = root_eval.raw().clamp(-200, 200);
-        (match self.board().side_to_move() == stm {
+        let clamped_eval = root_eval.raw().clamp(-aggression.limit, aggression.limit) as i32;
+        let aggression = (match self.board().side_to_move() == stm {
             true => scale * clamped_eval,
             false => -scale * clamped_eval,
-        }) / 100
+        }) / 100;
+        aggression.clamp(i16::MIN as i32, i16::MAX as i32) as i16
     }
 
     /// Calculates NN evaluation + FRC bonus
+    /// - Recognized endgames are scored by [endgame::evaluate] instead
     /// - Add [aggression](Self::aggression) if using for search results & pruning
     pub fn get_eval(&mut self) -> Evaluation {
+        if let Some(eval) = endgame::evaluate(self.board()) {
+            return eval;
+        }
         self.update_nnue();
         let frc_score = frc::frc_corner_bishop(self.board());
         let piece_cnt = self.board().occupied().len() as i16;
@@ -256,6 +288,25 @@ impl Position {
         }
     }
 
+    /// Verifies incrementally updated threats, pawn hash and accumulators
+    /// against the same state calculated from scratch
+    #[cfg(test)]
+    pub fn assert_consistent(&mut self) {
+        let board = &self.current;
+        let (w_threats, b_threats) = threats(board);
+        assert_eq!((self.w_threats, self.b_threats), (w_threats, b_threats));
+        let pawn_zobrist = Zobrist::new(
+            board.colored_pieces(Color::White, Piece::Pawn),
+            board.colored_pieces(Color::Black, Piece::Pawn),
+        );
+        assert_eq!(self.pawn_hash(), pawn_zobrist.hash());
+
+        self.update_nnue();
+        let mut evaluator = self.evaluator.clone();
+        evaluator.full_reset(&self.current, w_threats, b_threats);
+        assert!(self.evaluator.same_accumulator(&evaluator));
+    }
+
     /// Returns true if a move is capture
     /// - Excludes en-passant
     pub fn is_capture(&self, mv: Move) -> bool {
@@ -269,3 +320,21 @@ impl Position {
         mv.promotion.is_none() && !self.is_capture(mv)
     }
 }
+
+#[test]
+fn aggression_limits() {
+    let position = Position::new(Board::default());
+    let aggression = Aggression {
+        scale: 10,
+        limit: 1000,
+    };
+    let root_eval = Evaluation::new(1000);
+    assert_eq!(
+        position.aggression(Color::White, root_eval, aggression),
+        1600
+    );
+    assert_eq!(
+        position.aggression(Color::Black, root_eval, aggression),
+        -1600
+    );
+}
@@ -199,6 +199,13 @@ impl Entry {
         self.analysis[1].store(0, Ordering::Relaxed);
     }
 
+    fn load(&self) -> (u32, u64) {
+        let hash = self.hash.load(Ordering::Relaxed);
+        let entry_a = self.analysis[0].load(Ordering::Relaxed);
+        let entry_b = self.analysis[1].load(Ordering::Relaxed);
+        (hash, entry_a as u64 | ((entry_b as u64) << 32))
+    }
+
     fn set_new(&self, hash: u32, entry: u64) {
         self.hash.store(hash, Ordering::Relaxed);
         self.analysis[0].store(entry as u32, Ordering::Relaxed);
@@ -206,6 +213,18 @@ impl Entry {
     }
 }
 
+/// Runs `f` on equally sized chunks of `entries` in parallel
+/// - `f` receives the index of the first entry in the chunk
+fn for_each_chunk(entries: &[Entry], threads: usize, f: impl Fn(usize, &[Entry]) + Sync) {
+    let chunk_size = entries.len().div_ceil(threads.max(1)).max(1);
+    std::thread::scope(|scope| {
+        for (chunk_index, chunk) in entries.chunks(chunk_size).enumerate() {
+            let f = &f;
+            scope.spawn(move || f(chunk_index * chunk_size, chunk));
+        }
+    });
+}
+
 #[derive(Debug)]
 pub struct TranspositionTable {
     table: Box<[Entry]>,
@@ -311,12 +330,142 @@ impl TranspositionTable {
         new_depth * 2 + self.age_of(prev) as u32 + 1 >= prev_depth
     }
 
-    pub fn clean(&self) {
+    /// Zeroes all entries, splitting the work across threads
+    pub fn clean(&self, threads: usize) {
         self.age.store(0, Ordering::Relaxed);
-        self.table.iter().for_each(|entry| entry.zero());
+        for_each_chunk(&self.table, threads, |_, chunk| {
+            chunk.iter().for_each(Entry::zero)
+        });
+    }
+
+    /// Creates a table with the given number of entries
+    /// - If `rehash` is set, entries of this table are moved into the new table
+    /// - Entries only store part of their hash, so an entry is copied
+    ///   to every slot its position could map to in the new table
+    /// - Only the first copy keeps its age, the others are aged by one search
+    ///   so they don't count towards [TranspositionTable::hashfull] and are replaced first
+    pub fn resize(&self, size: usize, threads: usize, rehash: bool) -> Self {
+        let resized = Self::new(size);
+        if !rehash {
+            return resized;
+        }
+        resized
+            .age
+            .store(self.age.load(Ordering::Relaxed), Ordering::Relaxed);
+        let old_len = self.table.len() as u128;
+        let new_len = size as u128;
+        for_each_chunk(&self.table, threads, |start, chunk| {
+            for (offset, entry) in chunk.iter().enumerate() {
+                let (hash, raw) = entry.load();
+                let Some(analysis) = Analysis::from_raw(raw) else {
+                    continue;
+                };
+                // Range of hashes that map to this entry's index
+                let index = (start + offset) as u128;
+                let lowest = (index << 64).div_ceil(old_len);
+                let highest = ((index + 1) << 64).div_ceil(old_len) - 1;
+                let first = ((lowest * new_len) >> 64) as usize;
+                let last = ((highest * new_len) >> 64) as usize;
+                let stale = Analysis {
+                    age: match self.age_of(&analysis) {
+                        0 => analysis.age.wrapping_sub(1),
+                        _ => analysis.age,
+                    },
+                    ..analysis
+                };
+                for (new_index, new_entry) in (first..=last).zip(&resized.table[first..=last]) {
+                    let copy = match new_index == first {
+                        true => analysis,
+                        false => stale,
+                    };
+                    let previous = Analysis::from_raw(new_entry.load().1);
+                    if previous.is_none_or(|previous| resized.replace(&copy, &previous)) {
+                        new_entry.set_new(hash, copy.to_raw());
+                    }
+                }
+            }
+        });
+        resized
+    }
+
+    /// Permille of sampled entries written during the current search
+    pub fn hashfull(&self) -> u32 {
+        let sample = &self.table[..self.table.len().min(1000)];
+        let used = sample
+            .iter()
+            .filter_map(|entry| Analysis::from_raw(entry.load().1))
+            .filter(|analysis| self.age_of(analysis) == 0)
+            .count();
+        (used * 1000 / sample.len().max(1)) as u32
     }
 
     pub fn age(&self) {
         self.age.fetch_add(1, Ordering::Relaxed);
     }
 }
+
+#[test]
+fn rehash_keeps_entries() {
+    let boards = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    ]
+    .map(|fen| fen.parse::<Board>().unwrap());
+    let table = TranspositionTable::new(1000);
+    for (depth, board) in boards.iter().enumerate() {
+        table.set(
+            board,
+            depth as u32 + 1,
+            Bounds::Exact,
+            Evaluation::new(depth as i16),
+            None,
+            Evaluation::new(0),
+        );
+    }
+    for size in [1000, 3333, 100_000, 5000, 500] {
+        let resized = table.resize(size, 3, true);
+        for (depth, board) in boards.iter().enumerate() {
+            let entry = resized.get(board).unwrap();
+            assert_eq!(entry.depth, depth as u32 + 1);
+            assert_eq!(entry.score, Evaluation::new(depth as i16));
+        }
+    }
+    assert!(table.resize(5000, 3, false).get(&boards[0]).is_none());
+
+    assert!(table.hashfull() > 0);
+    table.age();
+    assert_eq!(table.hashfull(), 0);
+
+    table.clean(3);
+    assert!(boards.iter().all(|board| table.get(board).is_none()));
+}
+
+#[test]
+fn rehash_hashfull() {
+    let table = TranspositionTable::new(1000);
+    let startpos = Board::default();
+    startpos.generate_moves(|moves| {
+        for make_move in moves {
+            let mut board = startpos.clone();
+            board.play_unchecked(make_move);
+            board.generate_moves(|replies| {
+                for reply in replies {
+                    let mut board = board.clone();
+                    board.play_unchecked(reply);
+                    let eval = Evaluation::new(0);
+                    table.set(&board, 1, Bounds::Exact, eval, None, eval);
+                }
+                false
+            });
+        }
+        false
+    });
+    // The sample of the grown table covers the first quarter of this table
+    let used = table.table[..250]
+        .iter()
+        .filter(|entry| Analysis::from_raw(entry.load().1).is_some())
+        .count();
+    assert!(used > 0);
+    assert_eq!(table.resize(4000, 3, true).hashfull(), used as u32);
+}
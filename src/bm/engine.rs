@@ -0,0 +1,430 @@
+//! Public engine API, protocol adapters and library users drive the search through [Engine]
+//!
+//! All moves taken and returned by the API are in UCI notation: castling is king to
+//! destination square unless Chess960 is enabled, in which case it is king captures rook.
+
+use std::fmt::Display;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use cozy_chess::{Board, Move};
+
+use crate::bm::bm_runner::ab_runner::AbRunner;
+use crate::bm::bm_runner::config::{Run, Score, SearchObserver, SearchResult};
+use crate::bm::bm_runner::skill::Skill;
+use crate::bm::bm_runner::time::{TimeManagementInfo, TimeManager};
+use crate::bm::bm_util::position::{Aggression, Position};
+use crate::bm::nnue::{Nnue, NnueError};
+use crate::bm::tablebase::{LocalTablebase, Tablebase};
+use crate::bm::uci;
+
+/// Limits of a single search, unset limits don't constrain the search
+/// - Without any time control the search runs until depth, nodes or mate limits are reached
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    pub wtime: Option<Duration>,
+    pub btime: Option<Duration>,
+    pub winc: Option<Duration>,
+    pub binc: Option<Duration>,
+    pub moves_to_go: Option<u32>,
+    pub move_time: Option<Duration>,
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    /// Stop as soon as a mate in this many moves or less is found
+    pub mate: Option<u32>,
+    /// Search until stopped
+    pub infinite: bool,
+    /// Search without time limits until stopped or the ponder move is played
+    pub ponder: bool,
+    /// Root moves the search is restricted to, empty if all moves are allowed
+    pub search_moves: Vec<Move>,
+}
+
+impl SearchLimits {
+    fn time_management_info(&self) -> Vec<TimeManagementInfo> {
+        let mut info = vec![];
+        info.extend(self.wtime.map(TimeManagementInfo::WTime));
+        info.extend(self.btime.map(TimeManagementInfo::BTime));
+        info.extend(self.winc.map(TimeManagementInfo::WInc));
+        info.extend(self.binc.map(TimeManagementInfo::BInc));
+        info.extend(self.moves_to_go.map(TimeManagementInfo::MovesToGo));
+        info.extend(self.move_time.map(TimeManagementInfo::MoveTime));
+        info.extend(self.depth.map(TimeManagementInfo::MaxDepth));
+        info.extend(self.nodes.map(TimeManagementInfo::MaxNodes));
+        info.extend(self.mate.map(TimeManagementInfo::Mate));
+        if self.infinite {
+            info.push(TimeManagementInfo::Infinite);
+        }
+        if self.ponder {
+            info.push(TimeManagementInfo::Ponder);
+        }
+        info
+    }
+}
+
+/// Move that isn't legal in the position it was played in
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct IllegalMove(pub Move);
+
+impl Display for IllegalMove {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "illegal move {}", self.0)
+    }
+}
+
+impl std::error::Error for IllegalMove {}
+
+/// None of the moves the search is restricted to are legal
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct NoSearchMoves;
+
+impl Display for NoSearchMoves {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "none of the search moves are legal")
+    }
+}
+
+impl std::error::Error for NoSearchMoves {}
+
+/// Stops or releases a running search from another thread
+#[derive(Debug, Clone)]
+pub struct StopHandle {
+    time_manager: Arc<TimeManager>,
+}
+
+impl StopHandle {
+    /// Ends the current search as soon as possible, the search still returns its best move
+    pub fn stop(&self) {
+        self.time_manager.abort_now();
+    }
+
+    /// The expected reply was played, a ponder search continues with normal time limits
+    pub fn ponder_hit(&self) {
+        self.time_manager.ponder_hit();
+    }
+}
+
+/// Chess engine searching a single position at a time
+pub struct Engine {
+    runner: AbRunner,
+    time_manager: Arc<TimeManager>,
+    chess960: bool,
+    contempt: i16,
+    /// Contempt is disabled in analysis
+    analyse_mode: bool,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Engine {
+    /// Creates an engine at the starting position with a 16 MB transposition table and one thread
+    pub fn new() -> Self {
+        let time_manager = Arc::new(TimeManager::new());
+        Self {
+            runner: AbRunner::new(Board::default(), time_manager.clone()),
+            time_manager,
+            chess960: false,
+            contempt: 0,
+            analyse_mode: false,
+        }
+    }
+
+    /// Handle that stops searches of this engine from other threads
+    pub fn stop_handle(&self) -> StopHandle {
+        StopHandle {
+            time_manager: self.time_manager.clone(),
+        }
+    }
+
+    /// Resizes the transposition table, keeping existing entries if `rehash` is set
+    pub fn set_hash(&mut self, hash_mb: usize, rehash: bool) {
+        self.runner.hash(hash_mb, rehash);
+    }
+
+    pub fn clear_hash(&self) {
+        self.runner.clear_hash();
+    }
+
+    pub fn set_threads(&mut self, threads: u16) {
+        self.runner.set_threads(threads.max(1));
+    }
+
+    /// Sets the number of principal variations reported at each depth
+    pub fn set_multi_pv(&mut self, multi_pv: usize) {
+        self.runner.set_multi_pv(multi_pv);
+    }
+
+    /// Switches castling moves to Chess960 notation
+    pub fn set_chess960(&mut self, chess960: bool) {
+        self.chess960 = chess960;
+        self.runner.set_chess960(chess960);
+    }
+
+    pub fn chess960(&self) -> bool {
+        self.chess960
+    }
+
+    /// Reports win, draw and loss chances in search updates
+    pub fn set_show_wdl(&mut self, show_wdl: bool) {
+        self.runner.set_uci_show_wdl(show_wdl);
+    }
+
+    /// Time reserved for communication latency on every move
+    pub fn set_move_overhead(&mut self, move_overhead: Duration) {
+        self.time_manager.set_move_overhead(move_overhead);
+    }
+
+    /// Measures time budgets in nodes searched per millisecond, 0 uses the clock
+    pub fn set_nodes_time(&mut self, nodes_time: u32) {
+        self.time_manager.set_nodes_time(nodes_time);
+    }
+
+    /// Makes results depend only on the position and search limits
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.runner.set_deterministic(deterministic);
+    }
+
+    /// Limits playing strength to a level between 0 and 20, 20 is full strength
+    pub fn set_skill_level(&mut self, level: u8) {
+        self.runner.set_skill(Skill::from_level(level));
+    }
+
+    /// Sets the centipawns the engine loses by drawing
+    pub fn set_contempt(&mut self, contempt: i16) {
+        self.contempt = contempt;
+        self.update_contempt();
+    }
+
+    /// Analysis evaluates positions objectively, disabling contempt
+    pub fn set_analyse_mode(&mut self, analyse_mode: bool) {
+        self.analyse_mode = analyse_mode;
+        self.update_contempt();
+    }
+
+    fn update_contempt(&mut self) {
+        let contempt = match self.analyse_mode {
+            true => 0,
+            false => self.contempt,
+        };
+        self.runner.set_contempt(contempt);
+    }
+
+    /// Sets how much the engine prefers keeping pieces on the board
+    pub fn set_aggression(&mut self, aggression: Aggression) {
+        self.runner.set_aggression(aggression);
+    }
+
+    /// Loads a network file, None restores the network embedded at build time
+    /// - The current network is kept if loading fails
+    pub fn set_network(&mut self, path: Option<&Path>) -> Result<(), NnueError> {
+        let nnue = match path {
+            Some(path) => Nnue::from_file(path)?,
+            None => Nnue::new(),
+        };
+        self.runner.set_nnue(nnue);
+        Ok(())
+    }
+
+    /// Enables probing the built-in KPK and KRK tables
+    pub fn set_tablebase(&mut self, enabled: bool) {
+        let tablebase: Option<Arc<dyn Tablebase>> = match enabled {
+            true => Some(Arc::new(LocalTablebase::new())),
+            false => None,
+        };
+        self.runner.set_tablebase(tablebase);
+    }
+
+    /// Forgets previous searches and returns to the starting position
+    pub fn new_game(&mut self) {
+        self.runner.new_game();
+        self.time_manager.clear();
+        self.time_manager.new_game();
+        self.runner.set_board(Board::default());
+    }
+
+    /// Sets up a position and plays the moves on it
+    /// - Stops at the first illegal move, keeping the moves played before it
+    pub fn set_position(&mut self, board: Board, moves: &[Move]) -> Result<(), IllegalMove> {
+        self.runner.set_board(board);
+        for &make_move in moves {
+            self.play_move(make_move)?;
+        }
+        Ok(())
+    }
+
+    pub fn play_move(&mut self, make_move: Move) -> Result<(), IllegalMove> {
+        let mut converted = make_move;
+        uci::convert_move(&mut converted, self.board(), self.chess960);
+        if !self.board().is_legal(converted) {
+            return Err(IllegalMove(make_move));
+        }
+        self.runner.make_move(converted);
+        Ok(())
+    }
+
+    pub fn board(&self) -> &Board {
+        self.runner.get_board()
+    }
+
+    pub(crate) fn position(&self) -> &Position {
+        self.runner.get_position()
+    }
+
+    /// Side to move relative network evaluation of the current position
+    pub fn static_eval(&mut self) -> i16 {
+        self.runner.raw_eval().raw()
+    }
+
+    /// Searches the current position, blocking until the search ends
+    /// - Infinite and ponder searches only end after [StopHandle::stop] or [StopHandle::ponder_hit]
+    /// - The observer receives the result through [SearchObserver::complete] before it's returned
+    /// - Illegal moves in [SearchLimits::search_moves] are ignored, the search fails if none are legal
+    pub fn search<O: SearchObserver + 'static>(
+        &mut self,
+        limits: &SearchLimits,
+        observer: O,
+    ) -> Result<SearchResult, NoSearchMoves> {
+        self.start_search(limits)?;
+        Ok(self.run_search(observer))
+    }
+
+    /// Applies the limits of the next search, returning the ignored illegal search moves
+    /// - Protocol adapters call this before handing the search to a worker thread,
+    ///   so a stop arriving right after is not lost
+    /// - Nothing is prepared if none of the search moves are legal
+    pub(crate) fn start_search(
+        &mut self,
+        limits: &SearchLimits,
+    ) -> Result<Vec<IllegalMove>, NoSearchMoves> {
+        let board = self.board().clone();
+        let mut search_moves = vec![];
+        let mut illegal = vec![];
+        for &make_move in &limits.search_moves {
+            let mut converted = make_move;
+            uci::convert_move(&mut converted, &board, self.chess960);
+            match board.is_legal(converted) {
+                true => search_moves.push(converted),
+                false => illegal.push(IllegalMove(make_move)),
+            }
+        }
+        if !limits.search_moves.is_empty() && search_moves.is_empty() {
+            return Err(NoSearchMoves);
+        }
+        self.runner.set_search_moves(search_moves);
+        self.time_manager
+            .initiate(&board, &limits.time_management_info());
+        Ok(illegal)
+    }
+
+    /// Runs a search prepared by [Engine::start_search]
+    pub(crate) fn run_search<O: SearchObserver + 'static>(&mut self, observer: O) -> SearchResult {
+        let observer = Arc::new(Mutex::new(observer));
+        let board = self.board();
+        if !board.generate_moves(|_| true) {
+            // Nothing to search, infinite and ponder searches still wait to be released
+            self.time_manager.wait_for_release();
+            let score = match board.checkers().is_empty() {
+                true => Score::Cp(0),
+                false => Score::Mate(0),
+            };
+            let result = SearchResult {
+                best_move: None,
+                ponder_move: None,
+                score,
+                depth: 0,
+                nodes: 0,
+            };
+            observer.lock().unwrap().complete(&result);
+            return result;
+        }
+        let (mut best_move, ponder_move, eval, depth, nodes) =
+            self.runner.search::<Run>(observer.clone());
+        self.time_manager.wait_for_release();
+
+        let board = self.board();
+        let mut child = board.clone();
+        child.play_unchecked(best_move);
+        let ponder_move = ponder_move.map(|mut ponder_move| {
+            uci::convert_move_to_uci(&mut ponder_move, &child, self.chess960);
+            ponder_move
+        });
+        uci::convert_move_to_uci(&mut best_move, board, self.chess960);
+        let result = SearchResult {
+            best_move: Some(best_move),
+            ponder_move,
+            score: self.runner.reported_eval(eval).into(),
+            depth,
+            nodes,
+        };
+        observer.lock().unwrap().complete(&result);
+        result
+    }
+}
+
+#[test]
+fn position_moves() {
+    crate::bm::with_search_stack(|| {
+        let mut engine = Engine::new();
+        let board: Board = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1".parse().unwrap();
+        let moves = ["e1g1".parse().unwrap(), "e8e1".parse().unwrap()];
+        assert_eq!(
+            engine.set_position(board.clone(), &moves),
+            Err(IllegalMove(moves[1]))
+        );
+        assert_eq!(engine.board().side_to_move(), cozy_chess::Color::Black);
+
+        engine.set_chess960(true);
+        assert!(engine.set_position(board, &moves[..1]).is_err());
+        assert!(engine.play_move("e1h1".parse().unwrap()).is_ok());
+    });
+}
+
+#[test]
+fn terminal_positions() {
+    use crate::bm::bm_runner::config::NoObserver;
+
+    crate::bm::with_search_stack(|| {
+        let mut engine = Engine::new();
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+        let mate = "7k/6Q1/6K1/8/8/8/8/8 b - - 0 1".parse().unwrap();
+        engine.set_position(mate, &[]).unwrap();
+        let result = engine.search(&limits, NoObserver).unwrap();
+        assert_eq!(result.best_move, None);
+        assert_eq!(result.score, Score::Mate(0));
+
+        let stalemate = "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1".parse().unwrap();
+        engine.set_position(stalemate, &[]).unwrap();
+        let result = engine.search(&limits, NoObserver).unwrap();
+        assert_eq!(result.best_move, None);
+        assert_eq!(result.score, Score::Cp(0));
+    });
+}
+
+#[test]
+fn illegal_search_moves() {
+    crate::bm::with_search_stack(|| {
+        let mut engine = Engine::new();
+        let legal: Move = "e2e4".parse().unwrap();
+        let illegal: Move = "e2e5".parse().unwrap();
+        let limits = SearchLimits {
+            depth: Some(1),
+            search_moves: vec![illegal],
+            ..SearchLimits::default()
+        };
+        assert_eq!(engine.start_search(&limits), Err(NoSearchMoves));
+
+        let limits = SearchLimits {
+            search_moves: vec![legal, illegal],
+            ..limits
+        };
+        assert_eq!(engine.start_search(&limits), Ok(vec![IllegalMove(illegal)]));
+    });
+}
@@ -0,0 +1,509 @@
+//! JSON-lines analysis protocol, selected with `--json`
+//!
+//! Every request is a JSON object on a single line with a `cmd` and an optional `id`.
+//! Responses echo the `id` of their request and carry a `type`:
+//! - `analyse` streams `update` objects for every completed line and ends with a `result`
+//! - `stop` ends the running analysis, its `result` is still sent
+//! - `setoption` takes the same options as UCI, `options` lists them
+//!   Options set during an analysis are answered with an `info` and applied once it ends
+//! - `newgame`, `isready` and `quit` behave like their UCI counterparts
+//!
+//! Malformed requests are answered with an `error` object.
+
+use std::fmt::Display;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use cozy_chess::{Board, Move};
+
+use crate::bm::bm_runner::config::{Score, SearchObserver, SearchResult, SearchUpdate};
+use crate::bm::bm_util::t_table::Bounds;
+use crate::bm::engine::{Engine, SearchLimits, StopHandle};
+use crate::bm::uci::options::{self, EngineOptions, OptionKind, OptionValue};
+
+mod value;
+
+use value::{object, JsonValue, ParseError};
+
+/// Reasons a request can't be executed
+#[derive(Debug, Clone, PartialEq)]
+pub enum RequestError {
+    Syntax(ParseError),
+    NotAnObject,
+    MissingField(&'static str),
+    /// Field has the wrong type or an out of range value
+    InvalidField(&'static str),
+    UnknownCommand(String),
+    InvalidFen(String),
+    InvalidMove(String),
+}
+
+impl Display for RequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequestError::Syntax(err) => write!(f, "invalid json: {}", err),
+            RequestError::NotAnObject => write!(f, "request must be an object"),
+            RequestError::MissingField(field) => write!(f, "missing field {}", field),
+            RequestError::InvalidField(field) => write!(f, "invalid value for field {}", field),
+            RequestError::UnknownCommand(command) => write!(f, "unknown command {}", command),
+            RequestError::InvalidFen(fen) => write!(f, "invalid fen {}", fen),
+            RequestError::InvalidMove(make_move) => write!(f, "invalid move {}", make_move),
+        }
+    }
+}
+
+pub enum JsonCommand {
+    Analyse {
+        board: Box<Board>,
+        moves: Vec<Move>,
+        limits: SearchLimits,
+    },
+    Stop,
+    /// Option name and value, buttons have no value
+    SetOption(String, Option<String>),
+    Options,
+    NewGame,
+    IsReady,
+    Quit,
+}
+
+pub struct Request {
+    /// Echoed in every response, null if the request has none
+    id: JsonValue,
+    command: Result<JsonCommand, RequestError>,
+}
+
+fn u64_field(request: &JsonValue, field: &'static str) -> Result<Option<u64>, RequestError> {
+    request
+        .get(field)
+        .map(|value| value.as_u64().ok_or(RequestError::InvalidField(field)))
+        .transpose()
+}
+
+fn u32_field(request: &JsonValue, field: &'static str) -> Result<Option<u32>, RequestError> {
+    u64_field(request, field)?
+        .map(|value| u32::try_from(value).map_err(|_| RequestError::InvalidField(field)))
+        .transpose()
+}
+
+fn millis_field(
+    request: &JsonValue,
+    field: &'static str,
+) -> Result<Option<Duration>, RequestError> {
+    Ok(u64_field(request, field)?.map(Duration::from_millis))
+}
+
+fn moves_field(request: &JsonValue, field: &'static str) -> Result<Vec<Move>, RequestError> {
+    let Some(moves) = request.get(field) else {
+        return Ok(vec![]);
+    };
+    moves
+        .as_array()
+        .ok_or(RequestError::InvalidField(field))?
+        .iter()
+        .map(|make_move| {
+            let make_move = make_move
+                .as_str()
+                .ok_or(RequestError::InvalidField(field))?;
+            make_move
+                .parse()
+                .map_err(|_| RequestError::InvalidMove(make_move.to_string()))
+        })
+        .collect()
+}
+
+/// Option values may be given as strings, numbers or booleans
+fn option_value(value: &JsonValue) -> Result<String, RequestError> {
+    match value {
+        JsonValue::String(value) => Ok(value.clone()),
+        JsonValue::Number(_) | JsonValue::Bool(_) => Ok(value.to_string()),
+        _ => Err(RequestError::InvalidField("value")),
+    }
+}
+
+impl JsonCommand {
+    fn parse(request: &JsonValue, chess960: bool) -> Result<Self, RequestError> {
+        let command = request
+            .get("cmd")
+            .ok_or(RequestError::MissingField("cmd"))?
+            .as_str()
+            .ok_or(RequestError::InvalidField("cmd"))?;
+        Ok(match command {
+            "analyse" | "analyze" => {
+                let board = match request.get("fen").map(|fen| fen.as_str()) {
+                    None | Some(Some("startpos")) => Board::default(),
+                    Some(Some(fen)) => Board::from_fen(fen, chess960)
+                        .map_err(|_| RequestError::InvalidFen(fen.to_string()))?,
+                    Some(None) => return Err(RequestError::InvalidField("fen")),
+                };
+                let infinite = match request.get("infinite") {
+                    Some(infinite) => infinite
+                        .as_bool()
+                        .ok_or(RequestError::InvalidField("infinite"))?,
+                    None => false,
+                };
+                let limits = SearchLimits {
+                    wtime: millis_field(request, "wtime")?,
+                    btime: millis_field(request, "btime")?,
+                    winc: millis_field(request, "winc")?,
+                    binc: millis_field(request, "binc")?,
+                    moves_to_go: u32_field(request, "movestogo")?,
+                    move_time: millis_field(request, "movetime")?,
+                    depth: u32_field(request, "depth")?,
+                    nodes: u64_field(request, "nodes")?,
+                    mate: u32_field(request, "mate")?,
+                    infinite,
+                    ponder: false,
+                    search_moves: moves_field(request, "searchmoves")?,
+                };
+                JsonCommand::Analyse {
+                    board: Box::new(board),
+                    moves: moves_field(request, "moves")?,
+                    limits,
+                }
+            }
+            "stop" => JsonCommand::Stop,
+            "setoption" => {
+                let name = request
+                    .get("name")
+                    .ok_or(RequestError::MissingField("name"))?
+                    .as_str()
+                    .ok_or(RequestError::InvalidField("name"))?;
+                let value = request.get("value").map(option_value).transpose()?;
+                JsonCommand::SetOption(name.to_string(), value)
+            }
+            "options" => JsonCommand::Options,
+            "newgame" => JsonCommand::NewGame,
+            "isready" => JsonCommand::IsReady,
+            "quit" => JsonCommand::Quit,
+            _ => return Err(RequestError::UnknownCommand(command.to_string())),
+        })
+    }
+}
+
+impl Request {
+    pub fn parse(input: &str, chess960: bool) -> Self {
+        let request = match JsonValue::parse(input) {
+            Ok(request) => request,
+            Err(err) => {
+                return Self {
+                    id: JsonValue::Null,
+                    command: Err(RequestError::Syntax(err)),
+                }
+            }
+        };
+        let id = request.get("id").cloned().unwrap_or(JsonValue::Null);
+        let command = match request {
+            JsonValue::Object(_) => JsonCommand::parse(&request, chess960),
+            _ => Err(RequestError::NotAnObject),
+        };
+        Self { id, command }
+    }
+}
+
+/// Prints a response to a request
+fn respond(id: &JsonValue, kind: &str, mut fields: Vec<(&str, JsonValue)>) {
+    fields.splice(0..0, [("id", id.clone()), ("type", kind.into())]);
+    println!("{}", object(fields));
+}
+
+fn score(score: Score) -> JsonValue {
+    match score {
+        Score::Cp(cp) => object(vec![("cp", cp.into())]),
+        Score::Mate(mate_in) => object(vec![("mate", mate_in.into())]),
+    }
+}
+
+fn moves(moves: &[Move]) -> JsonValue {
+    moves
+        .iter()
+        .map(|make_move| make_move.to_string())
+        .collect::<Vec<_>>()
+        .into()
+}
+
+/// Prints search progress of a request as `update` objects and the result as a `result` object
+struct JsonOutput {
+    id: JsonValue,
+}
+
+impl SearchObserver for JsonOutput {
+    fn update(&mut self, update: &SearchUpdate) {
+        let bound = match update.bound {
+            Bounds::LowerBound => "lower",
+            Bounds::Exact => "exact",
+            Bounds::UpperBound => "upper",
+        };
+        let nps = (update.nodes as u128 * 1000) / update.time.as_millis().max(1);
+        let wdl = update
+            .wdl
+            .map(|(win, draw, loss)| JsonValue::from(vec![win, draw, loss]));
+        respond(
+            &self.id,
+            "update",
+            vec![
+                ("depth", update.depth.into()),
+                ("seldepth", update.sel_depth.into()),
+                ("multipv", update.multi_pv.into()),
+                ("score", score(update.score)),
+                ("bound", bound.into()),
+                ("wdl", wdl.unwrap_or(JsonValue::Null)),
+                ("time", (update.time.as_millis() as u64).into()),
+                ("nodes", update.nodes.into()),
+                ("nps", (nps as u64).into()),
+                ("hashfull", update.hashfull.into()),
+                ("tbhits", update.tb_hits.into()),
+                ("pv", moves(&update.pv)),
+            ],
+        );
+    }
+
+    fn complete(&mut self, result: &SearchResult) {
+        respond(
+            &self.id,
+            "result",
+            vec![
+                (
+                    "bestmove",
+                    result
+                        .best_move
+                        .map(|make_move| make_move.to_string())
+                        .into(),
+                ),
+                (
+                    "ponder",
+                    result
+                        .ponder_move
+                        .map(|make_move| make_move.to_string())
+                        .into(),
+                ),
+                ("score", score(result.score)),
+                ("depth", result.depth.into()),
+                ("nodes", result.nodes.into()),
+            ],
+        );
+    }
+}
+
+fn option_json(option: &options::UciOption) -> JsonValue {
+    let mut fields = vec![("name", option.name.into())];
+    match option.kind {
+        OptionKind::Spin { default, min, max } => fields.extend([
+            ("type", "spin".into()),
+            ("default", default.into()),
+            ("min", min.into()),
+            ("max", max.into()),
+        ]),
+        OptionKind::Check { default } => {
+            fields.extend([("type", "check".into()), ("default", default.into())])
+        }
+        OptionKind::String { default } => {
+            fields.extend([("type", "string".into()), ("default", default.into())])
+        }
+        OptionKind::Button => fields.push(("type", "button".into())),
+    }
+    object(fields)
+}
+
+/// Applies an option, answering the request that set it with the resulting messages
+fn apply_option(
+    options: &mut EngineOptions,
+    engine: &mut Engine,
+    id: &JsonValue,
+    name: &'static str,
+    value: OptionValue,
+) {
+    for message in options.apply(engine, name, value) {
+        respond(id, "info", vec![("message", message.into())]);
+    }
+}
+
+pub struct JsonAdapter {
+    engine: Arc<Mutex<Engine>>,
+    stop_handle: StopHandle,
+
+    sender: Sender<JsonOutput>,
+    /// Signals that the worker thread finished an analysis
+    done: Receiver<()>,
+    analysing: bool,
+    options: EngineOptions,
+    /// Options set during an analysis with the id of their request
+    pending_options: Vec<(JsonValue, &'static str, OptionValue)>,
+}
+
+impl Default for JsonAdapter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JsonAdapter {
+    pub fn new() -> Self {
+        let engine = Engine::new();
+        let stop_handle = engine.stop_handle();
+        let engine = Arc::new(Mutex::new(engine));
+
+        let (tx, rx): (Sender<JsonOutput>, Receiver<JsonOutput>) = mpsc::channel();
+        let (done_tx, done_rx) = mpsc::channel();
+        let thread_engine = engine.clone();
+        std::thread::spawn(move || {
+            // Ends once the adapter is dropped
+            for output in rx {
+                thread_engine.lock().unwrap().run_search(output);
+                if done_tx.send(()).is_err() {
+                    return;
+                }
+            }
+        });
+        Self {
+            engine,
+            stop_handle,
+            sender: tx,
+            done: done_rx,
+            analysing: false,
+            options: EngineOptions::new(),
+            pending_options: vec![],
+        }
+    }
+
+    /// Stops the running analysis and waits for its result
+    fn finish_analysis(&mut self) {
+        if self.analysing {
+            self.stop_handle.stop();
+            self.done.recv().unwrap();
+            self.analysing = false;
+        }
+        self.apply_pending_options();
+    }
+
+    /// Applies options set during the analysis, which has to be over
+    fn apply_pending_options(&mut self) {
+        if self.pending_options.is_empty() {
+            return;
+        }
+        let engine = &mut *self.engine.lock().unwrap();
+        for (id, name, value) in self.pending_options.drain(..) {
+            apply_option(&mut self.options, engine, &id, name, value);
+        }
+    }
+
+    pub fn input(&mut self, input: &str) -> bool {
+        let Request { id, command } = Request::parse(input, self.options.chess960());
+        let command = match command {
+            Ok(command) => command,
+            Err(err) => {
+                respond(&id, "error", vec![("message", err.to_string().into())]);
+                return true;
+            }
+        };
+        // Results are only pending while analysing
+        if self.analysing && self.done.try_recv().is_ok() {
+            self.analysing = false;
+            self.apply_pending_options();
+        }
+        match command {
+            JsonCommand::Analyse {
+                board,
+                moves,
+                limits,
+            } => {
+                self.finish_analysis();
+                let engine = &mut *self.engine.lock().unwrap();
+                if let Err(err) = engine.set_position(*board, &moves) {
+                    respond(&id, "error", vec![("message", err.to_string().into())]);
+                    return true;
+                }
+                match engine.start_search(&limits) {
+                    Ok(illegal) => {
+                        for err in illegal {
+                            let message = format!("{}, ignoring it in searchmoves", err);
+                            respond(&id, "info", vec![("message", message.into())]);
+                        }
+                    }
+                    Err(err) => {
+                        respond(&id, "error", vec![("message", err.to_string().into())]);
+                        return true;
+                    }
+                }
+                self.sender.send(JsonOutput { id }).unwrap();
+                self.analysing = true;
+            }
+            JsonCommand::Stop => self.stop_handle.stop(),
+            JsonCommand::SetOption(name, value) => {
+                let option = options::find(&name);
+                match option.and_then(|option| Ok((option.name, option.parse(value.as_deref())?))) {
+                    Ok((name, value)) if self.analysing => {
+                        let message = format!("{} will apply once the analysis ends", name);
+                        respond(&id, "info", vec![("message", message.into())]);
+                        self.pending_options.push((id, name, value));
+                    }
+                    Ok((name, value)) => {
+                        let engine = &mut *self.engine.lock().unwrap();
+                        apply_option(&mut self.options, engine, &id, name, value);
+                    }
+                    Err(err) => respond(&id, "error", vec![("message", err.to_string().into())]),
+                }
+            }
+            JsonCommand::Options => {
+                let options = options::OPTIONS.iter().map(option_json).collect::<Vec<_>>();
+                respond(&id, "options", vec![("options", options.into())]);
+            }
+            JsonCommand::NewGame => {
+                self.finish_analysis();
+                self.engine.lock().unwrap().new_game();
+            }
+            JsonCommand::IsReady => respond(&id, "ready", vec![]),
+            JsonCommand::Quit => {
+                self.finish_analysis();
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[test]
+fn requests() {
+    let parse = |input: &str| Request::parse(input, false);
+
+    let request = parse(
+        r#"{"id":"a1","cmd":"analyse","fen":"8/8/8/8/8/8/8/K1k5 w - - 0 1","moves":["a1a2"],"movetime":500,"infinite":false}"#,
+    );
+    assert_eq!(request.id, JsonValue::from("a1"));
+    assert!(matches!(
+        request.command,
+        Ok(JsonCommand::Analyse { moves, limits, .. })
+            if moves.len() == 1 && limits.move_time == Some(Duration::from_millis(500))
+    ));
+    assert!(matches!(
+        parse(r#"{"id":2,"cmd":"setoption","name":"Hash","value":64}"#).command,
+        Ok(JsonCommand::SetOption(name, Some(value))) if name == "Hash" && value == "64"
+    ));
+
+    assert!(matches!(parse("{").command, Err(RequestError::Syntax(_))));
+    assert!(matches!(
+        parse("[]").command,
+        Err(RequestError::NotAnObject)
+    ));
+    assert!(matches!(
+        parse(r#"{"id":3}"#).command,
+        Err(RequestError::MissingField("cmd"))
+    ));
+    assert!(matches!(
+        parse(r#"{"cmd":"analyse","depth":-1}"#).command,
+        Err(RequestError::InvalidField("depth"))
+    ));
+    assert!(matches!(
+        parse(r#"{"cmd":"analyse","fen":"8/8 w"}"#).command,
+        Err(RequestError::InvalidFen(_))
+    ));
+    assert!(matches!(
+        parse(r#"{"cmd":"analyse","moves":["e2"]}"#).command,
+        Err(RequestError::InvalidMove(_))
+    ));
+    assert!(matches!(
+        parse(r#"{"cmd":"go"}"#).command,
+        Err(RequestError::UnknownCommand(_))
+    ));
+}
@@ -0,0 +1,419 @@
+//! Minimal JSON values, enough for single line requests and responses
+
+use std::fmt::{Display, Write};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    /// Fields keep their order, duplicate keys resolve to the first field
+    Object(Vec<(String, JsonValue)>),
+}
+
+/// Syntax error at a byte offset of the input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub position: usize,
+    pub message: &'static str,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.position)
+    }
+}
+
+/// Builds an object from borrowed keys
+pub fn object(fields: Vec<(&str, JsonValue)>) -> JsonValue {
+    JsonValue::Object(
+        fields
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+impl JsonValue {
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser {
+            bytes: input.as_bytes(),
+            position: 0,
+        };
+        let value = parser.value(0)?;
+        parser.skip_whitespace();
+        match parser.position == parser.bytes.len() {
+            true => Ok(value),
+            false => Err(parser.error("trailing characters")),
+        }
+    }
+
+    /// Looks up a field of an object
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(fields) => fields
+                .iter()
+                .find(|(field, _)| field == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            JsonValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns non-negative integers that are exactly representable
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            JsonValue::Number(number)
+                if number.fract() == 0.0 && (0.0..=9007199254740992.0).contains(number) =>
+            {
+                Some(*number as u64)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[JsonValue]> {
+        match self {
+            JsonValue::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for JsonValue {
+    fn from(string: &str) -> Self {
+        JsonValue::String(string.to_string())
+    }
+}
+
+impl From<String> for JsonValue {
+    fn from(string: String) -> Self {
+        JsonValue::String(string)
+    }
+}
+
+impl From<bool> for JsonValue {
+    fn from(value: bool) -> Self {
+        JsonValue::Bool(value)
+    }
+}
+
+macro_rules! from_number {
+    ($($number:ty),*) => {
+        $(impl From<$number> for JsonValue {
+            fn from(number: $number) -> Self {
+                JsonValue::Number(number as f64)
+            }
+        })*
+    };
+}
+
+from_number!(i16, i64, u16, u32, u64, usize);
+
+impl<T: Into<JsonValue>> From<Option<T>> for JsonValue {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(JsonValue::Null, Into::into)
+    }
+}
+
+impl<T: Into<JsonValue>> From<Vec<T>> for JsonValue {
+    fn from(values: Vec<T>) -> Self {
+        JsonValue::Array(values.into_iter().map(Into::into).collect())
+    }
+}
+
+fn write_string(f: &mut std::fmt::Formatter<'_>, string: &str) -> std::fmt::Result {
+    f.write_char('"')?;
+    for c in string.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+impl Display for JsonValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonValue::Null => f.write_str("null"),
+            JsonValue::Bool(value) => write!(f, "{}", value),
+            JsonValue::Number(number) if !number.is_finite() => f.write_str("null"),
+            JsonValue::Number(number) => write!(f, "{}", number),
+            JsonValue::String(string) => write_string(f, string),
+            JsonValue::Array(values) => {
+                f.write_char('[')?;
+                for (index, value) in values.iter().enumerate() {
+                    if index != 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                f.write_char(']')
+            }
+            JsonValue::Object(fields) => {
+                f.write_char('{')?;
+                for (index, (key, value)) in fields.iter().enumerate() {
+                    if index != 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+/// Nesting limit, keeps malicious input from overflowing the stack
+const MAX_DEPTH: usize = 64;
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &'static str) -> ParseError {
+        ParseError {
+            position: self.position,
+            message,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.bytes.get(self.position) {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.bytes.get(self.position).copied()
+    }
+
+    fn expect(&mut self, byte: u8, message: &'static str) -> Result<(), ParseError> {
+        match self.peek() == Some(byte) {
+            true => {
+                self.position += 1;
+                Ok(())
+            }
+            false => Err(self.error(message)),
+        }
+    }
+
+    fn literal(&mut self, literal: &str, value: JsonValue) -> Result<JsonValue, ParseError> {
+        match self.bytes[self.position..].starts_with(literal.as_bytes()) {
+            true => {
+                self.position += literal.len();
+                Ok(value)
+            }
+            false => Err(self.error("invalid literal")),
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<JsonValue, ParseError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("nesting too deep"));
+        }
+        match self.peek() {
+            Some(b'n') => self.literal("null", JsonValue::Null),
+            Some(b't') => self.literal("true", JsonValue::Bool(true)),
+            Some(b'f') => self.literal("false", JsonValue::Bool(false)),
+            Some(b'"') => Ok(JsonValue::String(self.string()?)),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(b'[') => {
+                self.position += 1;
+                let mut values = vec![];
+                if self.peek() == Some(b']') {
+                    self.position += 1;
+                    return Ok(JsonValue::Array(values));
+                }
+                loop {
+                    values.push(self.value(depth + 1)?);
+                    match self.peek() {
+                        Some(b',') => self.position += 1,
+                        Some(b']') => {
+                            self.position += 1;
+                            return Ok(JsonValue::Array(values));
+                        }
+                        _ => return Err(self.error("expected , or ]")),
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.position += 1;
+                let mut fields = vec![];
+                if self.peek() == Some(b'}') {
+                    self.position += 1;
+                    return Ok(JsonValue::Object(fields));
+                }
+                loop {
+                    if self.peek() != Some(b'"') {
+                        return Err(self.error("expected key"));
+                    }
+                    let key = self.string()?;
+                    self.expect(b':', "expected :")?;
+                    fields.push((key, self.value(depth + 1)?));
+                    match self.peek() {
+                        Some(b',') => self.position += 1,
+                        Some(b'}') => {
+                            self.position += 1;
+                            return Ok(JsonValue::Object(fields));
+                        }
+                        _ => return Err(self.error("expected , or }")),
+                    }
+                }
+            }
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn number(&mut self) -> Result<JsonValue, ParseError> {
+        let start = self.position;
+        while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') =
+            self.bytes.get(self.position)
+        {
+            self.position += 1;
+        }
+        // Number characters are ASCII, so the slice is valid UTF-8
+        let text = std::str::from_utf8(&self.bytes[start..self.position]).unwrap();
+        match text.parse::<f64>() {
+            Ok(number) if number.is_finite() => Ok(JsonValue::Number(number)),
+            _ => Err(ParseError {
+                position: start,
+                message: "invalid number",
+            }),
+        }
+    }
+
+    fn hex_escape(&mut self) -> Result<u32, ParseError> {
+        let digits = self
+            .bytes
+            .get(self.position..self.position + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error("invalid unicode escape"))?;
+        self.position += 4;
+        Ok(digits)
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        // Opening quote
+        self.position += 1;
+        let mut string = String::new();
+        loop {
+            let start = self.position;
+            while let Some(&byte) = self.bytes.get(self.position) {
+                if byte == b'"' || byte == b'\\' || byte < 0x20 {
+                    break;
+                }
+                self.position += 1;
+            }
+            // Input is a str and the run ends at an ASCII byte, so it's valid UTF-8
+            string.push_str(std::str::from_utf8(&self.bytes[start..self.position]).unwrap());
+            match self.bytes.get(self.position) {
+                Some(b'"') => {
+                    self.position += 1;
+                    return Ok(string);
+                }
+                Some(b'\\') => {
+                    self.position += 1;
+                    let escape = self.bytes.get(self.position).copied();
+                    self.position += 1;
+                    string.push(match escape {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            let mut code = self.hex_escape()?;
+                            if (0xD800..0xDC00).contains(&code)
+                                && self.bytes[self.position..].starts_with(b"\\u")
+                            {
+                                self.position += 2;
+                                let low = self.hex_escape()?;
+                                code =
+                                    0x10000 + ((code - 0xD800) << 10) + (low.wrapping_sub(0xDC00));
+                            }
+                            char::from_u32(code)
+                                .ok_or_else(|| self.error("invalid unicode escape"))?
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    });
+                }
+                Some(_) => return Err(self.error("control character in string")),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+}
+
+#[test]
+fn json_values() {
+    let input = r#" {"id": 3, "cmd":"analyse", "moves": ["e2e4", "e7e5"], "x": [true, null, -1.5e2],
+        "s": "a\"b\\c\u00e9\ud83d\ude00"} "#;
+    let value = JsonValue::parse(input).unwrap();
+    assert_eq!(value.get("id").and_then(JsonValue::as_u64), Some(3));
+    assert_eq!(
+        value.get("cmd").and_then(JsonValue::as_str),
+        Some("analyse")
+    );
+    assert_eq!(
+        value
+            .get("moves")
+            .and_then(JsonValue::as_array)
+            .map(<[_]>::len),
+        Some(2)
+    );
+    assert_eq!(
+        value.get("s").and_then(JsonValue::as_str),
+        Some("a\"b\\c\u{e9}\u{1F600}")
+    );
+    assert_eq!(JsonValue::parse(&value.to_string()), Ok(value));
+
+    assert_eq!(JsonValue::Number(-1.5).as_u64(), None);
+    assert_eq!(
+        object(vec![("a", "x\ny".into()), ("b", Some(1_u32).into())]).to_string(),
+        r#"{"a":"x\ny","b":1}"#
+    );
+    for invalid in [
+        "",
+        "{",
+        "[1,]",
+        r#"{"a" 1}"#,
+        "tru",
+        "1 2",
+        "\"\\x\"",
+        "\"a",
+    ] {
+        assert!(JsonValue::parse(invalid).is_err(), "{}", invalid);
+    }
+    assert!(JsonValue::parse(&"[".repeat(1000)).is_err());
+}
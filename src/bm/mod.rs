@@ -2,5 +2,21 @@ pub mod bm_console;
 pub mod bm_runner;
 pub mod bm_search;
 pub mod bm_util;
+pub mod engine;
+pub mod json;
 pub mod nnue;
+pub mod tablebase;
 pub mod uci;
+pub mod xboard;
+
+/// Runs a test on a thread with enough stack for unoptimized search frames,
+/// which don't fit on the default test thread stack
+#[cfg(test)]
+pub(crate) fn with_search_stack(test: impl FnOnce() + Send + 'static) {
+    std::thread::Builder::new()
+        .stack_size(64 << 20)
+        .spawn(test)
+        .unwrap()
+        .join()
+        .unwrap();
+}
@@ -0,0 +1,328 @@
+//! Network file format, shared by the build script and the runtime loader
+//!
+//! Versioned files start with the following little endian header:
+//!
+//! | Field       | Type      |
+//! |-------------|-----------|
+//! | magic       | `b"BMNN"` |
+//! | version     | u32       |
+//! | feature set | u32       |
+//! | input       | u32       |
+//! | mid         | u32       |
+//! | output      | u32       |
+//! | buckets     | u32       |
+//! | ft scale    | u32       |
+//! | scale       | u32       |
+//! | hash        | u64       |
+//!
+//! The hash is the FNV-1a hash of the payload that follows the header.
+//!
+//! Legacy files written by the trainer only contain the three layer sizes.
+//! They are accepted without a checksum and assumed to use the current feature set and scales.
+
+use std::fmt::Display;
+
+pub const MAGIC: [u8; 4] = *b"BMNN";
+pub const VERSION: u32 = 1;
+pub const HEADER_BYTES: usize = 44;
+const LEGACY_HEADER_BYTES: usize = 12;
+
+/// HalfKA with 32 king buckets and threat inputs
+pub const FEATURE_SET: u32 = 1;
+pub const FEATURES: usize = 32 * 2 * 7 * 64;
+pub const BUCKETS: usize = 8;
+pub const FT_SCALE: i16 = 255;
+pub const SCALE: i16 = 64;
+/// Largest layer size accepted from a header, far above any trained network
+pub const MAX_LAYER_SIZE: usize = 1 << 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub version: u32,
+    pub feature_set: u32,
+    pub input: usize,
+    pub mid: usize,
+    pub output: usize,
+    pub buckets: usize,
+    pub ft_scale: i16,
+    pub scale: i16,
+    pub hash: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormatError {
+    /// File ends before the header is complete
+    Truncated {
+        found: usize,
+    },
+    UnsupportedVersion(u32),
+    /// Payload size doesn't match the size implied by the header
+    PayloadSize {
+        expected: usize,
+        found: usize,
+    },
+    HashMismatch {
+        expected: u64,
+        found: u64,
+    },
+    /// Layer size exceeds [MAX_LAYER_SIZE]
+    LayerSize {
+        field: &'static str,
+        found: usize,
+    },
+    /// Payload size described by the header doesn't fit in memory
+    PayloadOverflow,
+    /// Header field doesn't match the architecture the reader expects
+    Mismatch {
+        field: &'static str,
+        expected: u64,
+        found: u64,
+    },
+}
+
+impl Display for FormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormatError::Truncated { found } => {
+                write!(f, "file is {} bytes, too short for a network header", found)
+            }
+            FormatError::UnsupportedVersion(version) => write!(
+                f,
+                "format version {} is not supported, expected {}",
+                version, VERSION
+            ),
+            FormatError::PayloadSize { expected, found } => write!(
+                f,
+                "payload is {} bytes but the header describes {} bytes, the file may be truncated",
+                found, expected
+            ),
+            FormatError::HashMismatch { expected, found } => write!(
+                f,
+                "payload hash {:016x} doesn't match header hash {:016x}, the file is corrupted",
+                found, expected
+            ),
+            FormatError::LayerSize { field, found } => write!(
+                f,
+                "{} is {}, larger than the maximum of {}",
+                field, found, MAX_LAYER_SIZE
+            ),
+            FormatError::PayloadOverflow => write!(f, "payload size overflows"),
+            FormatError::Mismatch {
+                field,
+                expected,
+                found,
+            } => write!(f, "{} is {}, expected {}", field, found, expected),
+        }
+    }
+}
+
+impl Header {
+    /// Creates a header for the current format version
+    pub fn new(input: usize, mid: usize, output: usize, payload: &[u8]) -> Self {
+        Self {
+            version: VERSION,
+            feature_set: FEATURE_SET,
+            input,
+            mid,
+            output,
+            buckets: BUCKETS,
+            ft_scale: FT_SCALE,
+            scale: SCALE,
+            hash: hash(payload),
+        }
+    }
+
+    /// Size of the weights and biases described by the header
+    /// - Fails if a layer size is above [MAX_LAYER_SIZE], header fields aren't trusted
+    pub fn payload_size(&self) -> Result<usize, FormatError> {
+        let layers = [
+            ("input layer size", self.input),
+            ("hidden layer size", self.mid),
+            ("output layer size", self.output),
+        ];
+        for (field, found) in layers {
+            if found > MAX_LAYER_SIZE {
+                return Err(FormatError::LayerSize { field, found });
+            }
+        }
+        let size = self
+            .input
+            .checked_mul(self.mid)
+            .and_then(|ft| ft.checked_add(self.mid))
+            .and_then(|ft| ft.checked_add(self.mid.checked_mul(self.output)?))
+            .and_then(|weights| weights.checked_add(self.output))
+            .and_then(|weights| weights.checked_mul(2));
+        size.ok_or(FormatError::PayloadOverflow)
+    }
+
+    /// Used by the build script to embed networks with an up to date header
+    #[allow(dead_code)]
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        let fields = [
+            self.version,
+            self.feature_set,
+            self.input as u32,
+            self.mid as u32,
+            self.output as u32,
+            self.buckets as u32,
+            self.ft_scale as u32,
+            self.scale as u32,
+        ];
+        for field in fields {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.hash.to_le_bytes());
+        bytes
+    }
+
+    /// Verifies the header describes the given architecture
+    pub fn check(&self, input: usize, mid: usize, output: usize) -> Result<(), FormatError> {
+        let fields: [(&'static str, u64, u64); 7] = [
+            ("feature set", FEATURE_SET as u64, self.feature_set as u64),
+            ("input layer size", input as u64, self.input as u64),
+            ("hidden layer size", mid as u64, self.mid as u64),
+            ("output layer size", output as u64, self.output as u64),
+            ("output bucket count", BUCKETS as u64, self.buckets as u64),
+            (
+                "feature transformer scale",
+                FT_SCALE as u64,
+                self.ft_scale as u64,
+            ),
+            ("output scale", SCALE as u64, self.scale as u64),
+        ];
+        for (field, expected, found) in fields {
+            if expected != found {
+                return Err(FormatError::Mismatch {
+                    field,
+                    expected,
+                    found,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_u32(bytes: &[u8], index: usize) -> u32 {
+    let start = index * 4;
+    u32::from_le_bytes(bytes[start..start + 4].try_into().unwrap())
+}
+
+/// Parses the header and verifies the payload against it
+/// - Returns the header and the payload
+pub fn read(bytes: &[u8]) -> Result<(Header, &[u8]), FormatError> {
+    if !bytes.starts_with(&MAGIC) {
+        return read_legacy(bytes);
+    }
+    if bytes.len() < HEADER_BYTES {
+        return Err(FormatError::Truncated { found: bytes.len() });
+    }
+    let fields = &bytes[MAGIC.len()..];
+    let version = read_u32(fields, 0);
+    if version != VERSION {
+        return Err(FormatError::UnsupportedVersion(version));
+    }
+    let header = Header {
+        version,
+        feature_set: read_u32(fields, 1),
+        input: read_u32(fields, 2) as usize,
+        mid: read_u32(fields, 3) as usize,
+        output: read_u32(fields, 4) as usize,
+        buckets: read_u32(fields, 5) as usize,
+        ft_scale: read_u32(fields, 6) as i16,
+        scale: read_u32(fields, 7) as i16,
+        hash: u64::from_le_bytes(bytes[HEADER_BYTES - 8..HEADER_BYTES].try_into().unwrap()),
+    };
+    let payload = &bytes[HEADER_BYTES..];
+    let expected = header.payload_size()?;
+    if payload.len() != expected {
+        return Err(FormatError::PayloadSize {
+            expected,
+            found: payload.len(),
+        });
+    }
+    let payload_hash = hash(payload);
+    if payload_hash != header.hash {
+        return Err(FormatError::HashMismatch {
+            expected: header.hash,
+            found: payload_hash,
+        });
+    }
+    Ok((header, payload))
+}
+
+fn read_legacy(bytes: &[u8]) -> Result<(Header, &[u8]), FormatError> {
+    if bytes.len() < LEGACY_HEADER_BYTES {
+        return Err(FormatError::Truncated { found: bytes.len() });
+    }
+    let payload = &bytes[LEGACY_HEADER_BYTES..];
+    let mut header = Header::new(
+        read_u32(bytes, 0) as usize,
+        read_u32(bytes, 1) as usize,
+        read_u32(bytes, 2) as usize,
+        &[],
+    );
+    let expected = header.payload_size()?;
+    if payload.len() != expected {
+        return Err(FormatError::PayloadSize {
+            expected,
+            found: payload.len(),
+        });
+    }
+    header.version = 0;
+    header.hash = hash(payload);
+    Ok((header, payload))
+}
+
+/// 64 bit FNV-1a
+pub fn hash(bytes: &[u8]) -> u64 {
+    const OFFSET: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+    bytes.iter().fold(OFFSET, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(PRIME)
+    })
+}
+
+#[test]
+fn header_round_trip() {
+    let payload = vec![1; Header::new(4, 2, 8, &[]).payload_size().unwrap()];
+    let header = Header::new(4, 2, 8, &payload);
+    let mut bytes = header.to_bytes();
+    bytes.extend_from_slice(&payload);
+
+    let (read_header, read_payload) = read(&bytes).unwrap();
+    assert_eq!(read_header, header);
+    assert_eq!(read_payload, &payload[..]);
+    assert!(read_header.check(4, 2, 8).is_ok());
+    assert!(read_header.check(4, 4, 8).is_err());
+
+    let last = bytes.len() - 1;
+    bytes[last] ^= 1;
+    assert!(matches!(
+        read(&bytes),
+        Err(FormatError::HashMismatch { .. })
+    ));
+    bytes.pop();
+    assert!(matches!(read(&bytes), Err(FormatError::PayloadSize { .. })));
+}
+
+#[test]
+fn oversized_header() {
+    let mut bytes = Header::new(u32::MAX as usize, u32::MAX as usize, 1, &[]).to_bytes();
+    bytes.extend_from_slice(&[0; 16]);
+    assert_eq!(
+        read(&bytes),
+        Err(FormatError::LayerSize {
+            field: "input layer size",
+            found: u32::MAX as usize
+        })
+    );
+
+    let mut legacy = [u32::MAX, 1 << 16, 1].map(u32::to_le_bytes).concat();
+    legacy.extend_from_slice(&[0; 16]);
+    assert!(matches!(
+        read(&legacy),
+        Err(FormatError::LayerSize { found, .. }) if found == u32::MAX as usize
+    ));
+}
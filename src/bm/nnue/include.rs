@@ -10,8 +10,13 @@ pub fn sparse_from_bytes_i16<const INPUT: usize, const OUTPUT: usize>(
         weights.push(i16::from_le_bytes([bytes[0], bytes[1]]))
     }
 
+    let layout = Layout::new::<Align<[[i16; OUTPUT]; INPUT]>>();
+    // Allocating zero sized layouts is undefined behaviour
+    if layout.size() == 0 {
+        return Box::new(Align([[0; OUTPUT]; INPUT]));
+    }
     let mut dense = unsafe {
-        let mem = std::alloc::alloc_zeroed(Layout::new::<Align<[[i16; OUTPUT]; INPUT]>>());
+        let mem = std::alloc::alloc_zeroed(layout);
         Box::<Align<[[i16; OUTPUT]; INPUT]>>::from_raw(mem as *mut _)
     };
 
@@ -45,6 +50,9 @@ pub fn dense_from_bytes_i8<
         weights.push(i8::from_le_bytes([byte]))
     }
     let mut dense = Box::new(Align([[T::default(); INPUT]; OUTPUT]));
+    if INPUT == 0 {
+        return dense;
+    }
     for (i, weights) in weights.chunks(INPUT).enumerate() {
         for (j, &weight) in weights.iter().enumerate() {
             dense.0[i][j] = T::from(weight);
@@ -2,9 +2,9 @@ use std::{ops::Range, sync::Arc};
 
 use cfg_if::cfg_if;
 
+use super::format::{FT_SCALE, SCALE};
+
 const UNITS: i16 = 400_i16;
-const FT_SCALE: i16 = 255;
-const SCALE: i16 = 64;
 const MIN: i16 = 0;
 const MAX: i16 = FT_SCALE;
 const SHIFT: i16 = 8;
@@ -1,12 +1,16 @@
+use std::fmt::Display;
+use std::path::Path;
 use std::sync::Arc;
 
 use arrayvec::ArrayVec;
 use cozy_chess::{BitBoard, Board, Color, File, Move, Piece, Rank, Square};
 
+use self::format::FormatError;
 use self::layers::{Align, Dense, Incremental};
 
 use super::bm_runner::ab_runner;
 
+mod format;
 mod include;
 mod layers;
 
@@ -14,6 +18,24 @@ include!(concat!(env!("OUT_DIR"), "/arch.rs"));
 
 const NN_BYTES: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/eval.bin"));
 
+const _: () = assert!(INPUT == format::FEATURES);
+
+/// Reasons a network can't be loaded
+#[derive(Debug)]
+pub enum NnueError {
+    Io(std::io::Error),
+    Format(FormatError),
+}
+
+impl Display for NnueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NnueError::Io(err) => write!(f, "{}", err),
+            NnueError::Format(err) => write!(f, "{}", err),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Accumulator {
     w_acc: Align<[i16; MID]>,
@@ -111,8 +133,35 @@ pub struct Nnue {
 }
 
 impl Nnue {
+    /// Builds the network embedded at compile time
+    /// - The embedded network is verified by the build script
     pub fn new() -> Self {
-        let mut bytes = &NN_BYTES[12..];
+        Self::from_payload(&NN_BYTES[format::HEADER_BYTES..])
+    }
+
+    /// Builds a network from a file with the same architecture as the embedded network
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, NnueError> {
+        let bytes = std::fs::read(path).map_err(NnueError::Io)?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// Verifies the header, payload hash and architecture before building the network
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, NnueError> {
+        let (header, payload) = format::read(bytes).map_err(NnueError::Format)?;
+        header
+            .check(INPUT, MID, OUTPUT)
+            .map_err(NnueError::Format)?;
+        Ok(Self::from_payload(payload))
+    }
+
+    /// Returns false if the embedded network has no hidden layer
+    /// - An empty network is only embedded when the build is run with `EMPTY_NET` set
+    pub fn has_embedded_network() -> bool {
+        MID != 0
+    }
+
+    fn from_payload(payload: &[u8]) -> Self {
+        let mut bytes = payload;
         let incremental = Arc::from(include::sparse_from_bytes_i16::<INPUT, MID>(bytes));
         bytes = &bytes[INPUT * MID * 2..];
         let incremental_bias = include::bias_from_bytes_i16::<i16, MID>(bytes);
@@ -122,8 +171,6 @@ impl Nnue {
         ));
         bytes = &bytes[MID * OUTPUT * 2..];
         let out_bias = include::bias_from_bytes_i16::<i32, OUTPUT>(bytes);
-        bytes = &bytes[OUTPUT * 2..];
-        assert!(bytes.is_empty(), "{}", bytes.len());
 
         let input_layer = Incremental::new(incremental);
         let out_layer = Dense::new(out, out_bias);
@@ -256,6 +303,14 @@ impl Nnue {
         self.reset(Color::Black, board, w_threats, b_threats);
     }
 
+    /// Returns true if the current accumulators of both networks are equal
+    #[cfg(test)]
+    pub fn same_accumulator(&self, other: &Nnue) -> bool {
+        let acc = &self.accumulator[self.head];
+        let other = &other.accumulator[other.head];
+        acc.w_acc.0 == other.w_acc.0 && acc.b_acc.0 == other.b_acc.0
+    }
+
     fn push_accumulator(&mut self) {
         self.null_moves.push(false);
         self.head += 1;
@@ -421,7 +476,7 @@ impl Nnue {
         layers::sq_clipped_relu(stm, &mut incr.0);
         layers::sq_clipped_relu(nstm, &mut incr.0[MID..]);
 
-        let bucket = (((63 - piece_cnt) * (32 - piece_cnt)) / 225).min(7);
+        let bucket = (((63 - piece_cnt) * (32 - piece_cnt)) / 225).min(format::BUCKETS - 1);
         layers::scale_network_output(self.out_layer.feed_forward(&incr, bucket))
     }
 }
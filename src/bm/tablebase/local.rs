@@ -0,0 +1,342 @@
+//! Tables generated at startup covering king and pawn or king and rook against a lone king
+//!
+//! KPK and KRK are solved with retrograde analysis when the tables are created.
+//! Positions are stored from the point of view of the side with the extra piece,
+//! flipping the board vertically if that side is black.
+//!
+//! The stored distance is the number of plies to mate or to a winning promotion,
+//! which is used as the DTZ since both reset the fifty move counter or end the game.
+
+use std::collections::VecDeque;
+use std::sync::OnceLock;
+
+use cozy_chess::{
+    get_bishop_moves, get_king_moves, get_pawn_attacks, get_rook_moves, BitBoard, Board, Color,
+    Piece, Rank, Square,
+};
+
+use super::{Tablebase, Wdl};
+
+const STATES: usize = 2 * Square::NUM * Square::NUM * Square::NUM;
+/// Marks positions the strong side can't force a win from
+const DRAW: u8 = u8::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Material {
+    Kpk,
+    Krk,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct State {
+    strong_to_move: bool,
+    strong_king: Square,
+    weak_king: Square,
+    piece: Square,
+}
+
+impl State {
+    fn from_index(index: usize) -> Self {
+        Self {
+            strong_to_move: index >> 18 == 0,
+            strong_king: Square::index(index >> 12 & 63),
+            weak_king: Square::index(index >> 6 & 63),
+            piece: Square::index(index & 63),
+        }
+    }
+
+    fn index(&self) -> usize {
+        (!self.strong_to_move as usize) << 18
+            | (self.strong_king as usize) << 12
+            | (self.weak_king as usize) << 6
+            | self.piece as usize
+    }
+
+    fn occupied(&self) -> BitBoard {
+        self.strong_king.bitboard() | self.weak_king.bitboard() | self.piece.bitboard()
+    }
+
+    fn attacks(&self, material: Material, blockers: BitBoard) -> BitBoard {
+        match material {
+            Material::Kpk => get_pawn_attacks(self.piece, Color::White),
+            Material::Krk => get_rook_moves(self.piece, blockers),
+        }
+    }
+
+    fn weak_in_check(&self, material: Material) -> bool {
+        self.attacks(material, self.occupied()).has(self.weak_king)
+    }
+
+    fn is_valid(&self, material: Material) -> bool {
+        let back_ranks = Rank::First.bitboard() | Rank::Eighth.bitboard();
+        if self.occupied().len() != 3 || get_king_moves(self.strong_king).has(self.weak_king) {
+            return false;
+        }
+        if material == Material::Kpk && back_ranks.has(self.piece) {
+            return false;
+        }
+        !self.strong_to_move || !self.weak_in_check(material)
+    }
+
+    /// Returns the number of legal weak king moves, including capturing an undefended piece
+    fn weak_moves(&self, material: Material) -> u8 {
+        let guarded = get_king_moves(self.strong_king);
+        let attacked = self.attacks(material, self.occupied() ^ self.weak_king.bitboard());
+        (get_king_moves(self.weak_king) & !guarded)
+            .into_iter()
+            .filter(|&to| to == self.piece || !attacked.has(to))
+            .count() as u8
+    }
+
+    /// Returns true if the pawn can promote to a queen or rook that is neither lost nor stalemates
+    fn wins_by_promotion(&self) -> bool {
+        if self.piece.rank() != Rank::Seventh {
+            return false;
+        }
+        let to = self.piece.offset(0, 1);
+        if to == self.strong_king || to == self.weak_king {
+            return false;
+        }
+        let guarded = get_king_moves(self.strong_king);
+        let weak_moves = get_king_moves(self.weak_king) & !guarded;
+        if weak_moves.has(to) {
+            return false;
+        }
+        let blockers = self.strong_king.bitboard() | to.bitboard();
+        [Piece::Queen, Piece::Rook].into_iter().any(|promotion| {
+            let mut attacked = get_rook_moves(to, blockers);
+            if promotion == Piece::Queen {
+                attacked |= get_bishop_moves(to, blockers);
+            }
+            attacked.has(self.weak_king) || !(weak_moves & !attacked).is_empty()
+        })
+    }
+
+    /// Positions with the weak side to move that lead to this position
+    fn weak_predecessors(&self, material: Material, mut f: impl FnMut(State)) {
+        for from in get_king_moves(self.weak_king) & !self.occupied() {
+            let state = State {
+                strong_to_move: false,
+                weak_king: from,
+                ..*self
+            };
+            if state.is_valid(material) {
+                f(state);
+            }
+        }
+    }
+
+    /// Positions with the strong side to move that lead to this position
+    fn strong_predecessors(&self, material: Material, mut f: impl FnMut(State)) {
+        let empty = !self.occupied();
+        for from in get_king_moves(self.strong_king) & empty {
+            let state = State {
+                strong_to_move: true,
+                strong_king: from,
+                ..*self
+            };
+            if state.is_valid(material) {
+                f(state);
+            }
+        }
+        let piece_from = match material {
+            Material::Krk => get_rook_moves(self.piece, self.occupied()) & empty,
+            Material::Kpk => {
+                let mut from = BitBoard::EMPTY;
+                if let Some(single) = self.piece.try_offset(0, -1).filter(|&sq| empty.has(sq)) {
+                    from |= single.bitboard();
+                    if self.piece.rank() == Rank::Fourth && empty.has(single.offset(0, -1)) {
+                        from |= single.offset(0, -1).bitboard();
+                    }
+                }
+                from
+            }
+        };
+        for from in piece_from {
+            let state = State {
+                strong_to_move: true,
+                piece: from,
+                ..*self
+            };
+            if state.is_valid(material) {
+                f(state);
+            }
+        }
+    }
+}
+
+/// Solves the material configuration, returning the distance to conversion for every state
+fn generate(material: Material) -> Vec<u8> {
+    let mut distances = vec![DRAW; STATES];
+    // Weak side replies that aren't known to lose yet
+    let mut replies = vec![0_u8; STATES];
+    let mut queue = VecDeque::new();
+    let mut promotions = vec![];
+    for index in 0..STATES {
+        let state = State::from_index(index);
+        if !state.is_valid(material) {
+            continue;
+        }
+        if state.strong_to_move {
+            if material == Material::Kpk && state.wins_by_promotion() {
+                promotions.push(index);
+            }
+        } else {
+            let moves = state.weak_moves(material);
+            replies[index] = moves;
+            if moves == 0 && state.weak_in_check(material) {
+                distances[index] = 0;
+                queue.push_back(index);
+            }
+        }
+    }
+    for index in promotions {
+        distances[index] = 1;
+        queue.push_back(index);
+    }
+
+    while let Some(index) = queue.pop_front() {
+        let state = State::from_index(index);
+        let distance = distances[index] + 1;
+        if state.strong_to_move {
+            state.weak_predecessors(material, |prev| {
+                let prev = prev.index();
+                replies[prev] -= 1;
+                if replies[prev] == 0 {
+                    distances[prev] = distance;
+                    queue.push_back(prev);
+                }
+            });
+        } else {
+            state.strong_predecessors(material, |prev| {
+                let prev = prev.index();
+                if distances[prev] == DRAW {
+                    distances[prev] = distance;
+                    queue.push_back(prev);
+                }
+            });
+        }
+    }
+    distances
+}
+
+/// Tables are generated once on first use and shared by every user
+fn table(material: Material) -> &'static [u8] {
+    static KPK: OnceLock<Vec<u8>> = OnceLock::new();
+    static KRK: OnceLock<Vec<u8>> = OnceLock::new();
+    match material {
+        Material::Kpk => KPK.get_or_init(|| generate(Material::Kpk)),
+        Material::Krk => KRK.get_or_init(|| generate(Material::Krk)),
+    }
+}
+
+/// Returns the side to move relative result and the distance to conversion
+fn probe(board: &Board, materials: &[Material]) -> Option<(Wdl, u32)> {
+    if board.occupied().len() != 3 {
+        return None;
+    }
+    let strong = match board.colors(Color::White).len() {
+        2 => Color::White,
+        _ => Color::Black,
+    };
+    let piece = (board.colors(strong) & !board.pieces(Piece::King)).next_square()?;
+    let material = match board.piece_on(piece)? {
+        Piece::Pawn => Material::Kpk,
+        Piece::Rook => Material::Krk,
+        _ => return None,
+    };
+    if !materials.contains(&material) {
+        return None;
+    }
+    let normalize = |square: Square| match strong {
+        Color::White => square,
+        Color::Black => square.flip_rank(),
+    };
+    let strong_to_move = board.side_to_move() == strong;
+    let state = State {
+        strong_to_move,
+        strong_king: normalize(board.king(strong)),
+        weak_king: normalize(board.king(!strong)),
+        piece: normalize(piece),
+    };
+    let distance = table(material)[state.index()];
+    Some(match (distance, strong_to_move) {
+        (DRAW, _) => (Wdl::Draw, 0),
+        (distance, true) => (Wdl::Win, distance as u32),
+        (distance, false) => (Wdl::Loss, distance as u32),
+    })
+}
+
+/// Generates the KPK table ahead of the first probe
+pub fn init_kpk() {
+    table(Material::Kpk);
+}
+
+/// Probes the KPK table, used by the endgame evaluation
+pub fn probe_kpk(board: &Board) -> Option<Wdl> {
+    probe(board, &[Material::Kpk]).map(|(wdl, _)| wdl)
+}
+
+/// KPK and KRK tables solved in memory
+#[derive(Debug, Clone)]
+pub struct LocalTablebase;
+
+impl LocalTablebase {
+    /// Generates the tables if they haven't been generated yet
+    pub fn new() -> Self {
+        table(Material::Kpk);
+        table(Material::Krk);
+        Self
+    }
+
+    fn probe(&self, board: &Board) -> Option<(Wdl, u32)> {
+        probe(board, &[Material::Kpk, Material::Krk])
+    }
+}
+
+impl Default for LocalTablebase {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tablebase for LocalTablebase {
+    fn max_pieces(&self) -> u32 {
+        3
+    }
+
+    fn probe_wdl(&self, board: &Board) -> Option<Wdl> {
+        self.probe(board).map(|(wdl, _)| wdl)
+    }
+
+    fn probe_dtz(&self, board: &Board) -> Option<i32> {
+        self.probe(board).map(|(wdl, distance)| match wdl {
+            Wdl::Win => distance as i32,
+            Wdl::Draw => 0,
+            Wdl::Loss => -(distance as i32),
+        })
+    }
+}
+
+#[test]
+fn local_tables() {
+    let tablebase = LocalTablebase::new();
+    let probe = |fen: &str| tablebase.probe(&fen.parse::<Board>().unwrap()).unwrap();
+
+    // Mate in one and the mated position
+    assert_eq!(probe("7k/8/6K1/8/8/8/8/R7 w - - 0 1"), (Wdl::Win, 1));
+    assert_eq!(probe("R6k/8/6K1/8/8/8/8/8 b - - 0 1"), (Wdl::Loss, 0));
+    // Undefended rook is captured
+    assert_eq!(probe("8/8/8/8/8/8/1k6/R5K1 b - - 0 1").0, Wdl::Draw);
+    assert_eq!(probe("8/8/8/8/8/8/1k6/R5K1 w - - 0 1").0, Wdl::Win);
+    // King in front of the pawn on the sixth rank wins regardless of the side to move
+    assert_eq!(probe("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1").0, Wdl::Win);
+    assert_eq!(probe("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1").0, Wdl::Loss);
+    // Opposition in front of the pawn holds
+    assert_eq!(probe("8/8/8/8/8/4k3/4P3/4K3 w - - 0 1").0, Wdl::Draw);
+    // Rook pawn with the defending king in the corner
+    assert_eq!(probe("k7/8/8/8/8/8/P7/K7 w - - 0 1").0, Wdl::Draw);
+    // Colors flipped
+    assert_eq!(probe("4k3/4p3/4K3/8/8/8/8/8 b - - 0 1").0, Wdl::Draw);
+    assert_eq!(probe("8/8/8/8/4p3/4k3/8/4K3 b - - 0 1").0, Wdl::Win);
+}
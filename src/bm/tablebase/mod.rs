@@ -0,0 +1,152 @@
+//! Endgame tablebase probing
+//!
+//! Search probes WDL tables below the root and returns exact scores for covered positions.
+//! At the root, DTZ tables restrict the search to moves that keep the result
+//! while making progress, so won endgames are converted despite the fifty move rule.
+
+use std::fmt::Debug;
+
+use cozy_chess::{Board, Color, GameStatus, Move, Piece};
+
+use crate::bm::bm_util::eval::Evaluation;
+
+mod local;
+
+pub use local::{init_kpk, probe_kpk, LocalTablebase};
+
+/// Side to move relative result with perfect play
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss,
+    Draw,
+    Win,
+}
+
+impl std::ops::Neg for Wdl {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        match self {
+            Wdl::Loss => Wdl::Win,
+            Wdl::Draw => Wdl::Draw,
+            Wdl::Win => Wdl::Loss,
+        }
+    }
+}
+
+impl Wdl {
+    /// Score of a tablebase result found `ply` plies from the root, draws are scored as `draw`
+    pub fn to_eval(self, ply: u32, draw: Evaluation) -> Evaluation {
+        match self {
+            Wdl::Win => Evaluation::new_tb_win(ply),
+            Wdl::Draw => draw,
+            Wdl::Loss => -Evaluation::new_tb_win(ply),
+        }
+    }
+}
+
+pub trait Tablebase: Debug + Send + Sync {
+    /// Largest number of pieces, kings included, covered by the tables
+    fn max_pieces(&self) -> u32;
+
+    /// Returns the result of the position or None if it isn't covered
+    fn probe_wdl(&self, board: &Board) -> Option<Wdl>;
+
+    /// Returns the signed number of plies to the next zeroing move or None if it isn't covered
+    /// - Positive if the side to move wins, negative if it loses and zero for draws
+    fn probe_dtz(&self, board: &Board) -> Option<i32>;
+}
+
+/// Tables can't be used with castling rights, and positions with too many pieces aren't covered
+pub fn probeable(tablebase: &dyn Tablebase, board: &Board) -> bool {
+    let castle_rights = [Color::White, Color::Black].into_iter().any(|color| {
+        let rights = board.castle_rights(color);
+        rights.short.is_some() || rights.long.is_some()
+    });
+    !castle_rights && board.occupied().len() <= tablebase.max_pieces()
+}
+
+/// Result and distance of a child position, relative to the side that made the move
+fn child_result(tablebase: &dyn Tablebase, child: &Board) -> Option<(Wdl, u32)> {
+    match child.status() {
+        GameStatus::Won => return Some((Wdl::Win, 0)),
+        GameStatus::Drawn => return Some((Wdl::Draw, 0)),
+        GameStatus::Ongoing => {}
+    }
+    let minors = child.pieces(Piece::Knight) | child.pieces(Piece::Bishop);
+    if child.occupied().len() == 2 || (child.occupied().len() == 3 && !minors.is_empty()) {
+        return Some((Wdl::Draw, 0));
+    }
+    if !probeable(tablebase, child) {
+        return None;
+    }
+    let wdl = tablebase.probe_wdl(child)?;
+    let dtz = tablebase.probe_dtz(child)?;
+    Some((-wdl, dtz.unsigned_abs()))
+}
+
+/// Returns the root moves that preserve the tablebase result
+/// - Winning moves are limited to the ones closest to zeroing, zeroing moves come first
+/// - Losing moves are limited to the ones furthest from zeroing
+/// - Returns None if the position isn't covered
+pub fn root_moves(tablebase: &dyn Tablebase, board: &Board) -> Option<Vec<Move>> {
+    if !probeable(tablebase, board) {
+        return None;
+    }
+    let wdl = tablebase.probe_wdl(board)?;
+    let dtz = tablebase.probe_dtz(board)?;
+    let mut ranked = vec![];
+    board.generate_moves(|piece_moves| {
+        for make_move in piece_moves {
+            let mut child = board.clone();
+            child.play_unchecked(make_move);
+            let result = match child_result(tablebase, &child) {
+                Some(result) => Some(result),
+                // Converting into an uncovered position is only known to win if it's the best move
+                None if wdl == Wdl::Win && dtz == 1 => Some((Wdl::Win, 0)),
+                None => None,
+            };
+            if let Some((_, distance)) = result.filter(|&(child_wdl, _)| child_wdl == wdl) {
+                // Pawn moves and captures reset the fifty move counter
+                let zeroing = board.piece_on(make_move.from) == Some(Piece::Pawn)
+                    || board.occupied().has(make_move.to);
+                ranked.push((make_move, if zeroing { 0 } else { distance }));
+            }
+        }
+        false
+    });
+    let best = match wdl {
+        Wdl::Win => ranked.iter().map(|&(_, distance)| distance).min(),
+        Wdl::Draw => Some(0),
+        Wdl::Loss => ranked.iter().map(|&(_, distance)| distance).max(),
+    }?;
+    let moves: Vec<Move> = ranked
+        .into_iter()
+        .filter(|&(_, distance)| wdl == Wdl::Draw || distance == best)
+        .map(|(make_move, _)| make_move)
+        .collect();
+    match moves.is_empty() {
+        true => None,
+        false => Some(moves),
+    }
+}
+
+#[test]
+fn root_moves_make_progress() {
+    let tablebase = LocalTablebase::new();
+    let board: Board = "7k/8/6K1/8/8/8/8/R7 w - - 0 1".parse().unwrap();
+    let moves = root_moves(&tablebase, &board).unwrap();
+    assert_eq!(moves, vec!["a1a8".parse::<Move>().unwrap()]);
+
+    // Only the promotion to a queen or rook keeps the win
+    let board: Board = "8/4P3/8/8/8/8/k7/4K3 w - - 0 1".parse().unwrap();
+    let mut moves = root_moves(&tablebase, &board).unwrap();
+    moves.sort_by_key(|make_move| make_move.promotion);
+    assert_eq!(
+        moves,
+        vec![
+            "e7e8r".parse::<Move>().unwrap(),
+            "e7e8q".parse::<Move>().unwrap()
+        ]
+    );
+}
@@ -0,0 +1,291 @@
+//! Matches between skill levels, used to measure the Elo scale of `UCI_Elo`
+//!
+//! Without a reference, adjacent levels play each other and the scale is relative to full strength.
+//! With a reference UCI engine of known rating, every level plays the reference directly,
+//! which anchors the scale to the rating list the reference is calibrated on.
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::{Arc, Mutex};
+
+use cozy_chess::{Board, Color, GameStatus, Move};
+
+use crate::bm::bm_runner::ab_runner::AbRunner;
+use crate::bm::bm_runner::config::{NoObserver, Run};
+use crate::bm::bm_runner::skill::{Skill, LEVEL_ELO, MAX_ELO};
+use crate::bm::bm_runner::time::{TimeManagementInfo, TimeManager};
+use crate::bm::bm_util::position::Position;
+
+use super::{bench, convert_move, convert_move_to_uci};
+
+/// Node limit of every move, above the limit of every skill level below full strength
+const NODES_PER_MOVE: u64 = 1_500_000;
+/// Milliseconds the reference engine thinks on every move
+const REFERENCE_MOVE_TIME: u32 = 1000;
+/// Games that aren't decided by then are scored as draws
+const MAX_PLIES: usize = 300;
+
+/// Reference engine of known strength, given as `calibrate <games> <path> <elo>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub path: String,
+    /// Strength the reference is limited to with `UCI_LimitStrength` and `UCI_Elo`
+    pub elo: i64,
+}
+
+trait Player {
+    fn new_game(&mut self, opening: &Board);
+
+    /// Returns the move to play in the current position
+    fn best_move(&mut self) -> Move;
+
+    fn make_move(&mut self, make_move: Move);
+}
+
+struct Level {
+    runner: AbRunner,
+    time_manager: Arc<TimeManager>,
+}
+
+impl Level {
+    fn new(level: u8) -> Self {
+        let time_manager = Arc::new(TimeManager::new());
+        let mut runner = AbRunner::new(Board::default(), time_manager.clone());
+        runner.set_skill(Skill::from_level(level));
+        Self {
+            runner,
+            time_manager,
+        }
+    }
+}
+
+impl Player for Level {
+    fn new_game(&mut self, opening: &Board) {
+        self.runner.new_game();
+        self.runner.set_board(opening.clone());
+    }
+
+    fn best_move(&mut self) -> Move {
+        let board = self.runner.get_board().clone();
+        let limits = [TimeManagementInfo::MaxNodes(NODES_PER_MOVE)];
+        self.time_manager.initiate(&board, &limits);
+        let (make_move, _, _, _, _) = self.runner.search::<Run>(Arc::new(Mutex::new(NoObserver)));
+        self.time_manager.clear();
+        make_move
+    }
+
+    fn make_move(&mut self, make_move: Move) {
+        self.runner.make_move(make_move);
+    }
+}
+
+/// UCI engine running as a child process
+struct ReferenceEngine {
+    process: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    opening: Board,
+    board: Board,
+    moves: Vec<String>,
+}
+
+impl ReferenceEngine {
+    fn new(reference: &Reference) -> std::io::Result<Self> {
+        let mut process = Command::new(&reference.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = process.stdin.take().unwrap();
+        let stdout = BufReader::new(process.stdout.take().unwrap());
+        let mut engine = Self {
+            process,
+            stdin,
+            stdout,
+            opening: Board::default(),
+            board: Board::default(),
+            moves: vec![],
+        };
+        engine.send("uci")?;
+        engine.wait_for("uciok")?;
+        engine.send("setoption name UCI_LimitStrength value true")?;
+        engine.send(&format!("setoption name UCI_Elo value {}", reference.elo))?;
+        engine.send("isready")?;
+        engine.wait_for("readyok")?;
+        Ok(engine)
+    }
+
+    fn send(&mut self, command: &str) -> std::io::Result<()> {
+        writeln!(self.stdin, "{}", command)?;
+        self.stdin.flush()
+    }
+
+    /// Returns the first line starting with `prefix`
+    fn wait_for(&mut self, prefix: &str) -> std::io::Result<String> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.stdout.read_line(&mut line)? == 0 {
+                return Err(std::io::ErrorKind::UnexpectedEof.into());
+            }
+            if line.starts_with(prefix) {
+                return Ok(line.trim().to_string());
+            }
+        }
+    }
+}
+
+impl Player for ReferenceEngine {
+    fn new_game(&mut self, opening: &Board) {
+        self.opening = opening.clone();
+        self.board = opening.clone();
+        self.moves.clear();
+        self.send("ucinewgame").unwrap();
+    }
+
+    fn best_move(&mut self) -> Move {
+        let mut position = format!("position fen {}", self.opening);
+        if !self.moves.is_empty() {
+            position = format!("{} moves {}", position, self.moves.join(" "));
+        }
+        self.send(&position).unwrap();
+        self.send(&format!("go movetime {}", REFERENCE_MOVE_TIME))
+            .unwrap();
+        let best_move = self.wait_for("bestmove").unwrap();
+        let mut make_move = best_move
+            .split_ascii_whitespace()
+            .nth(1)
+            .and_then(|make_move| make_move.parse().ok())
+            .expect("reference engine sent an invalid move");
+        convert_move(&mut make_move, &self.board, false);
+        make_move
+    }
+
+    fn make_move(&mut self, make_move: Move) {
+        let mut uci_move = make_move;
+        convert_move_to_uci(&mut uci_move, &self.board, false);
+        self.moves.push(uci_move.to_string());
+        self.board.play_unchecked(make_move);
+    }
+}
+
+impl Drop for ReferenceEngine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let _ = self.process.wait();
+    }
+}
+
+/// Plays a game from the opening, returning the score of white
+fn play_game(white: &mut dyn Player, black: &mut dyn Player, opening: &Board) -> f32 {
+    white.new_game(opening);
+    black.new_game(opening);
+    let mut position = Position::new(opening.clone());
+    for _ in 0..MAX_PLIES {
+        match position.board().status() {
+            GameStatus::Won => {
+                return match position.board().side_to_move() {
+                    Color::White => 0.0,
+                    Color::Black => 1.0,
+                }
+            }
+            GameStatus::Drawn => return 0.5,
+            GameStatus::Ongoing => {}
+        }
+        if position.forced_draw(1) {
+            return 0.5;
+        }
+        let make_move = match position.board().side_to_move() {
+            Color::White => white.best_move(),
+            Color::Black => black.best_move(),
+        };
+        white.make_move(make_move);
+        black.make_move(make_move);
+        position.make_move(make_move);
+    }
+    0.5
+}
+
+/// Plays a match alternating colors, returning the score of `player`
+fn play_match(player: &mut dyn Player, opponent: &mut dyn Player, games: u32) -> f32 {
+    let openings: Vec<Board> = bench::bench_positions().collect();
+    let mut score = 0.0;
+    for game in 0..games {
+        let opening = &openings[game as usize / 2 % openings.len()];
+        score += match game % 2 == 0 {
+            true => play_game(player, opponent, opening),
+            false => 1.0 - play_game(opponent, player, opening),
+        };
+    }
+    score
+}
+
+/// Elo difference implied by a score between 0 and 1
+fn elo_difference(score: f32) -> f32 {
+    let score = score.clamp(0.01, 0.99);
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+/// Measures the Elo of every calibrated level
+/// - With a reference, each level plays `games` games against it
+/// - Otherwise each level plays the next stronger one, relative to full strength at [MAX_ELO]
+/// - Prints the measured table in the format of [LEVEL_ELO]
+pub fn calibrate(games: u32, reference: Option<Reference>) {
+    if games == 0 {
+        println!("info string calibrate needs at least one game");
+        return;
+    }
+    let mut elo = vec![MAX_ELO as f32; LEVEL_ELO.len()];
+    match &reference {
+        Some(reference) => {
+            let mut reference_engine = match ReferenceEngine::new(reference) {
+                Ok(engine) => engine,
+                Err(err) => {
+                    println!("info string failed to start {}: {}", reference.path, err);
+                    return;
+                }
+            };
+            for (index, &(level, _)) in LEVEL_ELO.iter().enumerate() {
+                let score = play_match(&mut Level::new(level), &mut reference_engine, games);
+                let difference = elo_difference(score / games as f32);
+                elo[index] = reference.elo as f32 + difference;
+                println!(
+                    "level {:>2} vs reference: {:>5.1}/{} {:>+5.0} elo",
+                    level, score, games, difference
+                );
+            }
+        }
+        None => {
+            for index in (0..LEVEL_ELO.len() - 1).rev() {
+                let (weak_level, _) = LEVEL_ELO[index];
+                let (strong_level, _) = LEVEL_ELO[index + 1];
+                let score = play_match(
+                    &mut Level::new(weak_level),
+                    &mut Level::new(strong_level),
+                    games,
+                );
+                let difference = elo_difference(score / games as f32);
+                elo[index] = elo[index + 1] + difference;
+                println!(
+                    "level {:>2} vs {:>2}: {:>5.1}/{} {:>+5.0} elo",
+                    weak_level, strong_level, score, games, difference
+                );
+            }
+        }
+    }
+    match &reference {
+        Some(reference) => println!(
+            "// {} games per level against {} at UCI_Elo {}, {} nodes against {} ms per move",
+            games, reference.path, reference.elo, NODES_PER_MOVE, REFERENCE_MOVE_TIME
+        ),
+        None => println!(
+            "// {} games between adjacent levels at {} nodes per move, relative to {} at level {}",
+            games,
+            NODES_PER_MOVE,
+            MAX_ELO,
+            LEVEL_ELO[LEVEL_ELO.len() - 1].0
+        ),
+    }
+    for (&(level, _), elo) in LEVEL_ELO.iter().zip(elo) {
+        println!("({}, {:.0}),", level, elo);
+    }
+}
@@ -1,132 +1,324 @@
+use std::fmt::Display;
+use std::str::FromStr;
 use std::time::Duration;
 
 use cozy_chess::{Board, Move};
 
-use crate::bm::bm_runner::time::TimeManagementInfo;
+use crate::bm::engine::SearchLimits;
+
+use super::calibrate::Reference;
 
 pub enum UciCommand {
     Uci,
     IsReady,
     NewGame,
     Position(Board, Vec<Move>),
-    Go(Vec<TimeManagementInfo>),
-    SetOption(String, String),
+    Go(SearchLimits),
+    PonderHit,
+    /// Option name and value, buttons have no value
+    SetOption(String, Option<String>),
     Move(Move),
     Bench(u32),
+    Perft(u32),
+    /// Number of games played per calibrated skill level and an optional reference engine
+    Calibrate(u32, Option<Reference>),
     Empty,
     Stop,
     Quit,
     Eval,
     Static,
+    /// Describes the current position
+    Display,
+}
+
+/// Reasons a command can't be parsed or executed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UciError {
+    UnknownCommand(String),
+    /// Keyword is missing its value
+    MissingValue(&'static str),
+    /// Value of a keyword can't be parsed
+    InvalidValue(&'static str, String),
+    UnknownOption(String),
+    /// Option is set without a value
+    MissingOptionValue(String),
+    InvalidOptionValue {
+        name: &'static str,
+        value: String,
+    },
+    /// Spin value is outside of the advertised range
+    OutOfRange {
+        name: &'static str,
+        value: i64,
+        min: i64,
+        max: i64,
+    },
+    /// Position command has neither `startpos` nor `fen`
+    MissingPosition,
+    InvalidFen(String),
+    InvalidMove(String),
+}
+
+impl Display for UciError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UciError::UnknownCommand(command) => write!(f, "unknown command {}", command),
+            UciError::MissingValue(key) => write!(f, "missing value for {}", key),
+            UciError::InvalidValue(key, value) => {
+                write!(f, "invalid value {} for {}", value, key)
+            }
+            UciError::UnknownOption(name) => write!(f, "unknown option {}", name),
+            UciError::MissingOptionValue(name) => {
+                write!(f, "missing value for option {}", name)
+            }
+            UciError::InvalidOptionValue { name, value } => {
+                write!(f, "invalid value {} for option {}", value, name)
+            }
+            UciError::OutOfRange {
+                name,
+                value,
+                min,
+                max,
+            } => write!(
+                f,
+                "value {} for option {} is outside of {}..{}",
+                value, name, min, max
+            ),
+            UciError::MissingPosition => write!(f, "position requires startpos or fen"),
+            UciError::InvalidFen(fen) => write!(f, "invalid fen {}", fen),
+            UciError::InvalidMove(make_move) => write!(f, "invalid move {}", make_move),
+        }
+    }
 }
 
+/// Parses the token following a keyword
+fn parse_value<'a, T: FromStr>(
+    split: &mut impl Iterator<Item = &'a str>,
+    key: &'static str,
+) -> Result<T, UciError> {
+    let value = split.next().ok_or(UciError::MissingValue(key))?;
+    value
+        .parse()
+        .map_err(|_| UciError::InvalidValue(key, value.to_string()))
+}
+
+/// Parses a time in milliseconds, negative times are treated as zero
+fn parse_millis<'a>(
+    split: &mut impl Iterator<Item = &'a str>,
+    key: &'static str,
+) -> Result<Duration, UciError> {
+    let millis = parse_value::<i64>(split, key)?;
+    Ok(Duration::from_millis(millis.max(0) as u64))
+}
+
+fn parse_move(token: &str) -> Result<Move, UciError> {
+    token
+        .parse()
+        .map_err(|_| UciError::InvalidMove(token.to_string()))
+}
+
+/// Keywords of `go`, which end the move list of `searchmoves`
+const GO_KEYWORDS: &[&str] = &[
+    "wtime",
+    "btime",
+    "winc",
+    "binc",
+    "movetime",
+    "movestogo",
+    "depth",
+    "nodes",
+    "ponder",
+    "infinite",
+    "mate",
+    "perft",
+    "searchmoves",
+];
+
 impl UciCommand {
-    pub fn parse(input: &str, chess960: bool) -> Self {
-        let input_move = input.parse();
+    pub fn parse(input: &str, chess960: bool) -> Result<Self, UciError> {
+        let input_move = input.trim().parse();
         if let Ok(m) = input_move {
-            return UciCommand::Move(m);
+            return Ok(UciCommand::Move(m));
         }
         let mut split = input.split_ascii_whitespace();
         let token = match split.next() {
             Some(string) => string,
-            None => return UciCommand::Empty,
+            None => return Ok(UciCommand::Empty),
         };
-        match token {
+        Ok(match token {
             "uci" => UciCommand::Uci,
             "ucinewgame" => UciCommand::NewGame,
             "position" => {
-                let mut board = String::new();
-                let mut chess_board = None;
-                let split = split.into_iter().collect::<Vec<_>>();
-
-                let mut board_end = 0;
-                for (index, token) in split.iter().enumerate() {
-                    let token = token.trim();
-                    if token == "startpos" {
-                        chess_board = Some(Board::default());
-                        board_end = index + 1;
-                        break;
-                    } else if token != "fen" {
-                        if token == "moves" {
-                            if let Ok(board) = Board::from_fen(board.trim(), chess960) {
-                                chess_board = Some(board);
-                                board_end = index;
-                                break;
-                            }
-                        }
-                        board += token;
-                        board += " ";
+                let split = split.collect::<Vec<_>>();
+                let moves_start = split
+                    .iter()
+                    .position(|&token| token == "moves")
+                    .unwrap_or(split.len());
+                let (setup, moves) = split.split_at(moves_start);
+                let board = match setup {
+                    ["startpos"] => Board::default(),
+                    [] | ["fen"] => return Err(UciError::MissingPosition),
+                    ["fen", fen @ ..] | fen => {
+                        let fen = fen.join(" ");
+                        Board::from_fen(&fen, chess960).map_err(|_| UciError::InvalidFen(fen))?
                     }
-                }
-                if chess_board.is_none() {
-                    chess_board = Some(Board::from_fen(board.trim(), chess960).unwrap());
-                }
-                let mut moves = vec![];
-                if board_end < split.len() && split[board_end] == "moves" {
-                    for token in &split[board_end + 1..] {
-                        let make_move = token.parse().unwrap();
-                        moves.push(make_move);
-                    }
-                }
-                UciCommand::Position(chess_board.unwrap(), moves)
+                };
+                let moves = moves
+                    .iter()
+                    .skip(1)
+                    .map(|token| parse_move(token))
+                    .collect::<Result<Vec<_>, _>>()?;
+                UciCommand::Position(board, moves)
             }
             "go" => {
-                let mut commands = vec![];
+                let mut limits = SearchLimits::default();
+                let mut split = split.peekable();
                 while let Some(option) = split.next() {
-                    commands.push(match option {
-                        "wtime" => {
-                            let millis = split.next().unwrap().parse::<i64>().unwrap();
-                            let millis = millis.max(0) as u64;
-                            TimeManagementInfo::WTime(Duration::from_millis(millis))
-                        }
-                        "btime" => {
-                            let millis = split.next().unwrap().parse::<i64>().unwrap();
-                            let millis = millis.max(0) as u64;
-                            TimeManagementInfo::BTime(Duration::from_millis(millis))
-                        }
-                        "winc" => {
-                            let millis = split.next().unwrap().parse().unwrap();
-                            TimeManagementInfo::WInc(Duration::from_millis(millis))
-                        }
-                        "binc" => {
-                            let millis = split.next().unwrap().parse().unwrap();
-                            TimeManagementInfo::BInc(Duration::from_millis(millis))
-                        }
+                    match option {
+                        "wtime" => limits.wtime = Some(parse_millis(&mut split, "wtime")?),
+                        "btime" => limits.btime = Some(parse_millis(&mut split, "btime")?),
+                        "winc" => limits.winc = Some(parse_millis(&mut split, "winc")?),
+                        "binc" => limits.binc = Some(parse_millis(&mut split, "binc")?),
                         "movetime" => {
-                            let millis = split.next().unwrap().parse().unwrap();
-                            TimeManagementInfo::MoveTime(Duration::from_millis(millis))
+                            limits.move_time = Some(parse_millis(&mut split, "movetime")?)
                         }
                         "movestogo" => {
-                            let moves_to_go = split.next().unwrap().parse().unwrap();
-                            TimeManagementInfo::MovesToGo(moves_to_go)
+                            limits.moves_to_go = Some(parse_value(&mut split, "movestogo")?)
                         }
-                        "depth" => {
-                            let depth = split.next().unwrap().parse().unwrap();
-                            TimeManagementInfo::MaxDepth(depth)
-                        }
-                        "nodes" => {
-                            let nodes = split.next().unwrap().parse().unwrap();
-                            TimeManagementInfo::MaxNodes(nodes)
+                        "depth" => limits.depth = Some(parse_value(&mut split, "depth")?),
+                        "nodes" => limits.nodes = Some(parse_value(&mut split, "nodes")?),
+                        "ponder" => limits.ponder = true,
+                        "infinite" => limits.infinite = true,
+                        "mate" => limits.mate = Some(parse_value(&mut split, "mate")?),
+                        "perft" => return Ok(UciCommand::Perft(parse_value(&mut split, "perft")?)),
+                        "searchmoves" => {
+                            while let Some(token) =
+                                split.next_if(|token| !GO_KEYWORDS.contains(token))
+                            {
+                                limits.search_moves.push(parse_move(token)?);
+                            }
                         }
-                        _ => TimeManagementInfo::Unknown,
-                    });
+                        _ => {}
+                    }
                 }
-                UciCommand::Go(commands)
+                UciCommand::Go(limits)
             }
+            "ponderhit" => UciCommand::PonderHit,
             "stop" => UciCommand::Stop,
             "quit" => UciCommand::Quit,
             "eval" => UciCommand::Eval,
             "isready" => UciCommand::IsReady,
-            "bench" => UciCommand::Bench(split.next().map_or(12, |depth| depth.parse().unwrap())),
+            "bench" => match split.next() {
+                Some(depth) => UciCommand::Bench(
+                    depth
+                        .parse()
+                        .map_err(|_| UciError::InvalidValue("bench", depth.to_string()))?,
+                ),
+                None => UciCommand::Bench(12),
+            },
+            "perft" => UciCommand::Perft(parse_value(&mut split, "perft")?),
+            "calibrate" => {
+                let games = match split.next() {
+                    Some(games) => games
+                        .parse()
+                        .map_err(|_| UciError::InvalidValue("calibrate", games.to_string()))?,
+                    None => 20,
+                };
+                let reference = match split.next() {
+                    Some(path) => Some(Reference {
+                        path: path.to_string(),
+                        elo: parse_value(&mut split, "calibrate")?,
+                    }),
+                    None => None,
+                };
+                UciCommand::Calibrate(games, reference)
+            }
             "static" => UciCommand::Static,
+            "d" | "print" => UciCommand::Display,
             "setoption" => {
-                split.next();
-                let name = split.next().unwrap().to_string();
-                split.next();
-                let value = split.next().unwrap().to_string();
-                UciCommand::SetOption(name, value)
+                if split.next() != Some("name") {
+                    return Err(UciError::MissingValue("name"));
+                }
+                let split = split.collect::<Vec<_>>();
+                let (name, value) = match split.iter().position(|&token| token == "value") {
+                    Some(index) => (&split[..index], Some(split[index + 1..].join(" "))),
+                    None => (&split[..], None),
+                };
+                if name.is_empty() {
+                    return Err(UciError::MissingValue("name"));
+                }
+                UciCommand::SetOption(name.join(" "), value)
             }
-            _ => UciCommand::Empty,
-        }
+            "debug" | "register" => UciCommand::Empty,
+            _ => return Err(UciError::UnknownCommand(token.to_string())),
+        })
     }
 }
+
+#[test]
+fn malformed_commands() {
+    let parse = |input: &str| UciCommand::parse(input, false).err();
+
+    assert_eq!(parse("go wtime"), Some(UciError::MissingValue("wtime")));
+    assert_eq!(
+        parse("go depth x"),
+        Some(UciError::InvalidValue("depth", "x".to_string()))
+    );
+    assert_eq!(
+        parse("setoption value 1"),
+        Some(UciError::MissingValue("name"))
+    );
+    assert!(matches!(
+        UciCommand::parse("setoption name Move Overhead value 10", false),
+        Ok(UciCommand::SetOption(name, Some(value))) if name == "Move Overhead" && value == "10"
+    ));
+    assert!(matches!(
+        UciCommand::parse("setoption name Clear Hash", false),
+        Ok(UciCommand::SetOption(name, None)) if name == "Clear Hash"
+    ));
+    assert!(matches!(
+        UciCommand::parse("setoption name EvalFile value C:/my nets/net.bin", false),
+        Ok(UciCommand::SetOption(_, Some(value))) if value == "C:/my nets/net.bin"
+    ));
+    assert_eq!(parse("position"), Some(UciError::MissingPosition));
+    assert!(matches!(
+        parse("position fen 8/8/8 w - - 0 1"),
+        Some(UciError::InvalidFen(_))
+    ));
+    assert_eq!(
+        parse("position startpos moves e2e4 e7"),
+        Some(UciError::InvalidMove("e7".to_string()))
+    );
+    assert_eq!(
+        parse("go searchmoves e2e4 e7 depth 5"),
+        Some(UciError::InvalidMove("e7".to_string()))
+    );
+    assert!(matches!(
+        UciCommand::parse("go searchmoves e2e4 d2d4 depth 5", false),
+        Ok(UciCommand::Go(limits))
+            if limits.search_moves.len() == 2 && limits.depth == Some(5)
+    ));
+    assert_eq!(
+        parse("foo"),
+        Some(UciError::UnknownCommand("foo".to_string()))
+    );
+
+    assert!(matches!(
+        UciCommand::parse("position fen 8/8/8/8/8/8/8/K1k5 w - - 0 1 moves a1a2", false),
+        Ok(UciCommand::Position(_, moves)) if moves.len() == 1
+    ));
+    assert!(matches!(
+        UciCommand::parse("go wtime -5 btime 100", false),
+        Ok(UciCommand::Go(limits))
+            if limits.wtime == Some(Duration::ZERO) && limits.btime.is_some()
+    ));
+    assert!(matches!(
+        UciCommand::parse("calibrate 40 ./stockfish 1800", false),
+        Ok(UciCommand::Calibrate(40, Some(reference)))
+            if reference.path == "./stockfish" && reference.elo == 1800
+    ));
+    assert_eq!(
+        parse("calibrate 40 ./stockfish"),
+        Some(UciError::MissingValue("calibrate"))
+    );
+}
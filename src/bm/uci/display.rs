@@ -0,0 +1,80 @@
+use std::fmt::Write;
+
+use cozy_chess::{BitBoard, Color, File, Rank, Square};
+
+use crate::bm::bm_util::position::Position;
+
+fn squares(bitboard: BitBoard) -> String {
+    match bitboard.is_empty() {
+        true => "-".to_string(),
+        false => bitboard
+            .into_iter()
+            .map(|square| square.to_string())
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+/// Describes the position for the `d` command
+/// - Castling rights and the FEN use Shredder-FEN notation in Chess960
+pub fn display(position: &Position, chess960: bool, static_eval: i16) -> String {
+    let board = position.board();
+    let mut output = String::new();
+    let divider = " +---+---+---+---+---+---+---+---+";
+    for &rank in Rank::ALL.iter().rev() {
+        writeln!(&mut output, "{}", divider).unwrap();
+        for &file in &File::ALL {
+            let square = Square::new(file, rank);
+            let piece = match (board.piece_on(square), board.color_on(square)) {
+                (Some(piece), Some(Color::White)) => char::from(piece).to_ascii_uppercase(),
+                (Some(piece), _) => char::from(piece),
+                (None, _) => ' ',
+            };
+            write!(&mut output, " | {}", piece).unwrap();
+        }
+        writeln!(&mut output, " | {}", rank).unwrap();
+    }
+    writeln!(&mut output, "{}", divider).unwrap();
+    writeln!(&mut output, "   a   b   c   d   e   f   g   h").unwrap();
+    writeln!(&mut output).unwrap();
+
+    let fen = match chess960 {
+        true => format!("{:#}", board),
+        false => board.to_string(),
+    };
+    let castling = fen.split(' ').nth(2).unwrap_or("-").to_string();
+    let side_to_move = match board.side_to_move() {
+        Color::White => "white",
+        Color::Black => "black",
+    };
+    let (stm_threats, nstm_threats) = position.threats();
+    let (w_threats, b_threats) = match board.side_to_move() {
+        Color::White => (stm_threats, nstm_threats),
+        Color::Black => (nstm_threats, stm_threats),
+    };
+    writeln!(&mut output, "Fen: {}", fen).unwrap();
+    writeln!(&mut output, "Key: {:016X}", position.hash()).unwrap();
+    writeln!(&mut output, "Pawn key: {:04X}", position.pawn_hash()).unwrap();
+    writeln!(&mut output, "Side to move: {}", side_to_move).unwrap();
+    writeln!(&mut output, "Castling: {}", castling).unwrap();
+    writeln!(&mut output, "Checkers: {}", squares(board.checkers())).unwrap();
+    writeln!(&mut output, "Threats by white: {}", squares(w_threats)).unwrap();
+    writeln!(&mut output, "Threats by black: {}", squares(b_threats)).unwrap();
+    write!(&mut output, "Static eval: {}", static_eval).unwrap();
+    output
+}
+
+#[test]
+fn display_position() {
+    let board = "r3k1r1/8/8/3n4/4P3/8/8/1R2K2R b Kq - 0 1".parse().unwrap();
+    let position = Position::new(board);
+
+    let output = display(&position, false, 0);
+    assert!(output.contains(" | r |   |   |   | k |   | r |   | 8"));
+    assert!(output.contains("Castling: Kq"));
+    assert!(output.contains("Threats by white: d5"));
+    assert!(output.contains("Side to move: black"));
+
+    let output = display(&position, true, 0);
+    assert!(output.contains("Castling: Ha"));
+}
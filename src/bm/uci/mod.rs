@@ -1,58 +1,121 @@
+use std::fmt::Write;
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 use cozy_chess::{Board, File, Move, Piece, Square};
 
-use crate::bm::bm_runner::ab_runner::AbRunner;
-use crate::bm::bm_runner::config::{NoInfo, Run, UciInfo};
-
-use crate::bm::bm_runner::time::{TimeManagementInfo, TimeManager};
+use crate::bm::bm_runner::config::{NoObserver, SearchObserver, SearchResult, SearchUpdate};
+use crate::bm::bm_util::position::Position;
+use crate::bm::bm_util::t_table::Bounds;
+use crate::bm::engine::{Engine, SearchLimits, StopHandle};
+use crate::bm::nnue::Nnue;
 
 mod bench;
+mod calibrate;
 mod command;
+mod display;
+pub mod options;
+mod perft;
 
 use command::UciCommand;
+use options::{EngineOptions, OptionValue};
 
-const VERSION: &str = "9.0";
+pub(crate) const VERSION: &str = "9.0";
 
 enum ThreadReq {
-    Go(GoReq),
+    Go,
     Quit,
 }
 
-struct GoReq {
-    bm_runner: Arc<Mutex<AbRunner>>,
-    chess960: bool,
+/// Prints search progress as UCI `info` lines and the result as `bestmove`
+struct UciOutput;
+
+impl SearchObserver for UciOutput {
+    fn update(&mut self, info: &SearchUpdate) {
+        let mut score = info.score.to_string();
+        match info.bound {
+            Bounds::LowerBound => score.push_str(" lowerbound"),
+            Bounds::UpperBound => score.push_str(" upperbound"),
+            Bounds::Exact => {}
+        }
+        let nps = (info.nodes as u128 * 1000) / info.time.as_millis().max(1);
+        let wdl = match info.wdl {
+            Some((win, draw, loss)) => format!("wdl {} {} {} ", win, draw, loss),
+            None => "".to_string(),
+        };
+        let mut output = format!(
+            "info depth {} seldepth {} multipv {} score {} {}time {} nodes {} nps {} hashfull {} tbhits {} pv",
+            info.depth,
+            info.sel_depth,
+            info.multi_pv,
+            score,
+            wdl,
+            info.time.as_millis(),
+            info.nodes,
+            nps,
+            info.hashfull,
+            info.tb_hits
+        );
+        for make_move in &info.pv {
+            write!(&mut output, " {}", make_move).unwrap();
+        }
+        println!("{}", output);
+    }
+
+    fn current_move(&mut self, depth: u32, make_move: Move, move_number: usize) {
+        println!(
+            "info depth {} currmove {} currmovenumber {}",
+            depth, make_move, move_number
+        );
+    }
+
+    fn progress(&mut self, elapsed: Duration, nodes: u64, hashfull: u32) {
+        let nps = (nodes as u128 * 1000) / elapsed.as_millis().max(1);
+        println!(
+            "info time {} nodes {} nps {} hashfull {}",
+            elapsed.as_millis(),
+            nodes,
+            nps,
+            hashfull
+        );
+    }
+
+    fn complete(&mut self, result: &SearchResult) {
+        match (result.best_move, result.ponder_move) {
+            (Some(best_move), Some(ponder_move)) => {
+                println!("bestmove {} ponder {}", best_move, ponder_move)
+            }
+            (Some(best_move), None) => println!("bestmove {}", best_move),
+            // Null move for checkmated or stalemated positions
+            (None, _) => println!("bestmove 0000"),
+        }
+    }
 }
 
 pub struct UciAdapter {
-    bm_runner: Arc<Mutex<AbRunner>>,
-    time_manager: Arc<TimeManager>,
+    engine: Arc<Mutex<Engine>>,
+    stop_handle: StopHandle,
 
     sender: Sender<ThreadReq>,
-    forced: bool,
-    chess960: bool,
-    show_wdl: bool,
+    options: EngineOptions,
+    /// Options set while the engine was searching, applied once the search ends
+    pending_options: Vec<(&'static str, OptionValue)>,
 }
 
 impl UciAdapter {
     pub fn new() -> Self {
-        let time_manager = Arc::new(TimeManager::new());
-        let bm_runner = Arc::new(Mutex::new(AbRunner::new(
-            Board::default(),
-            time_manager.clone(),
-        )));
+        let engine = Engine::new();
+        let stop_handle = engine.stop_handle();
+        let engine = Arc::new(Mutex::new(engine));
 
         let (tx, rx): (Sender<ThreadReq>, Receiver<ThreadReq>) = mpsc::channel();
+        let thread_engine = engine.clone();
         std::thread::spawn(move || loop {
             if let Ok(req) = rx.recv() {
                 match req {
-                    ThreadReq::Go(req) => {
-                        let mut bm_runner = req.bm_runner.lock().unwrap();
-                        let (mut best_move, _, _, _) = bm_runner.search::<Run, UciInfo>();
-                        convert_move_to_uci(&mut best_move, bm_runner.get_board(), req.chess960);
-                        println!("bestmove {}", best_move);
+                    ThreadReq::Go => {
+                        thread_engine.lock().unwrap().run_search(UciOutput);
                     }
                     ThreadReq::Quit => {
                         return;
@@ -61,105 +124,106 @@ impl UciAdapter {
             }
         });
         Self {
-            bm_runner,
-            forced: false,
+            engine,
+            stop_handle,
             sender: tx,
-            time_manager,
-            chess960: false,
-            show_wdl: false,
+            options: EngineOptions::new(),
+            pending_options: vec![],
         }
     }
 
     pub fn input(&mut self, input: &str) -> bool {
+        self.apply_pending_options(false);
         let name = "Black Marlin".to_string();
-        let command = UciCommand::parse(&input, self.chess960);
+        let command = match UciCommand::parse(input, self.options.chess960()) {
+            Ok(command) => command,
+            Err(err) => {
+                println!("info string {}", err);
+                return true;
+            }
+        };
         match command {
             UciCommand::Uci => {
                 println!("id name {} {}", name, VERSION);
                 println!("id author Doruk S.");
-                println!("option name Hash type spin default 16 min 1 max 65536");
-                println!("option name Threads type spin default 1 min 1 max 65535");
-                println!("option name UCI_ShowWDL type check default false");
-                println!("option name UCI_Chess960 type check default false");
+                if !Nnue::has_embedded_network() {
+                    println!(
+                        "info string no network was embedded at build time, evaluation is disabled"
+                    );
+                }
+                for option in options::OPTIONS {
+                    println!("{}", option);
+                }
                 println!("uciok");
             }
             UciCommand::IsReady => println!("readyok"),
             UciCommand::Move(make_move) => {
-                let runner = &mut *self.bm_runner.lock().unwrap();
-                runner.make_move(make_move);
+                if let Err(err) = self.engine.lock().unwrap().play_move(make_move) {
+                    println!("info string {}", err);
+                }
             }
             UciCommand::Empty => {}
             UciCommand::Stop => {
-                self.time_manager.abort_now();
+                self.stop_handle.stop();
+            }
+            UciCommand::PonderHit => {
+                self.stop_handle.ponder_hit();
             }
             UciCommand::Quit => {
                 self.exit();
                 return false;
             }
             UciCommand::Eval => {
-                let runner = &mut *self.bm_runner.lock().unwrap();
-
-                println!("eval    : {}", runner.raw_eval().raw());
-            }
-            UciCommand::Go(commands) => self.go(commands),
-            UciCommand::NewGame => {
-                let runner = &mut *self.bm_runner.lock().unwrap();
-                runner.new_game();
-                runner.set_board(Board::default());
+                println!("eval    : {}", self.engine.lock().unwrap().static_eval());
             }
+            UciCommand::Go(limits) => self.go(&limits),
+            UciCommand::NewGame => self.engine.lock().unwrap().new_game(),
             UciCommand::Position(position, moves) => {
-                let runner = &mut *self.bm_runner.lock().unwrap();
-                runner.set_board(position);
-                for mut make_move in moves {
-                    convert_move(&mut make_move, runner.get_board(), self.chess960);
-                    runner.make_move(make_move);
+                if let Err(err) = self.engine.lock().unwrap().set_position(position, &moves) {
+                    println!("info string {}, ignoring the remaining moves", err);
                 }
             }
             UciCommand::SetOption(name, value) => {
-                self.time_manager.abort_now();
-                match name.as_str() {
-                    "Hash" => {
-                        self.bm_runner.lock().unwrap().hash(value.parse().unwrap());
-                    }
-                    "Threads" => {
-                        self.bm_runner
-                            .lock()
-                            .unwrap()
-                            .set_threads(value.parse().unwrap());
-                    }
-                    "UCI_Chess960" => {
-                        self.chess960 = value.to_lowercase().parse().unwrap();
-                        self.bm_runner.lock().unwrap().set_chess960(self.chess960);
-                    }
-                    "UCI_ShowWDL" => {
-                        self.show_wdl = value.to_lowercase().parse().unwrap();
-                        self.bm_runner
-                            .lock()
-                            .unwrap()
-                            .set_uci_show_wdl(self.show_wdl);
-                    }
-                    _ => {}
+                let option = options::find(&name);
+                match option.and_then(|option| Ok((option.name, option.parse(value.as_deref())?))) {
+                    // The search thread holds the engine until the search ends
+                    Ok((name, value)) => match self.engine.try_lock() {
+                        Ok(mut engine) => {
+                            for message in self.options.apply(&mut engine, name, value) {
+                                println!("info string {}", message);
+                            }
+                        }
+                        Err(_) => {
+                            println!("info string {} will apply to the next search", name);
+                            self.pending_options.push((name, value));
+                        }
+                    },
+                    Err(err) => println!("info string {}", err),
                 }
             }
             UciCommand::Bench(depth) => {
                 let mut bench_data = vec![];
 
-                let bm_runner = &mut *self.bm_runner.lock().unwrap();
+                let engine = &mut *self.engine.lock().unwrap();
                 let mut sum_node_cnt = 0;
                 let mut sum_time = Duration::from_nanos(0);
                 for board in bench::bench_positions() {
-                    bm_runner.new_game();
-                    bm_runner.set_board(board.clone());
-                    let options = [TimeManagementInfo::MaxDepth(depth)];
+                    engine.new_game();
+                    engine.set_position(board, &[]).unwrap();
+                    let limits = SearchLimits {
+                        depth: Some(depth),
+                        ..SearchLimits::default()
+                    };
                     let start = Instant::now();
 
-                    self.time_manager.initiate(&board, &options);
-                    let (make_move, eval, _, node_cnt) = bm_runner.search::<Run, NoInfo>();
-                    self.time_manager.clear();
+                    let result = engine.search(&limits, NoObserver).unwrap();
+                    let node_cnt = result.nodes;
                     let elapsed = start.elapsed();
                     bench_data.push((
-                        eval.raw(),
-                        make_move,
+                        result.score,
+                        result
+                            .best_move
+                            .map_or("0000".to_string(), |mv| mv.to_string()),
                         node_cnt,
                         (node_cnt as f32 / elapsed.as_secs_f32()) as u32,
                     ));
@@ -167,11 +231,11 @@ impl UciAdapter {
                     sum_node_cnt += node_cnt;
                 }
                 let mut divider_size = 0;
-                for (index, (cp, mv, nodes, nps)) in bench_data.into_iter().enumerate() {
+                for (index, (score, mv, nodes, nps)) in bench_data.into_iter().enumerate() {
                     let line = &format!(
-                        "[#{:>3}]{:>8} cp  Best: {:>8} {:>8} nodes {:>8} nps",
+                        "[#{:>3}]{:>11}  Best: {:>8} {:>8} nodes {:>8} nps",
                         index + 1,
-                        cp,
+                        score.to_string(),
                         mv,
                         nodes,
                         nps
@@ -186,30 +250,84 @@ impl UciAdapter {
                     (sum_node_cnt as f32 / sum_time.as_secs_f32()) as u32
                 );
             }
-            UciCommand::Static => {
-                let runner = &mut *self.bm_runner.lock().unwrap();
-                println!("{}", runner.raw_eval().raw());
+            UciCommand::Perft(depth) => {
+                let board = self.engine.lock().unwrap().board().clone();
+                let mut position = Position::new(board);
+                let start = Instant::now();
+                let mut total = 0;
+                for (make_move, nodes) in
+                    perft::divide(&mut position, depth, self.options.chess960())
+                {
+                    println!("{}: {}", make_move, nodes);
+                    total += nodes;
+                }
+                if depth == 0 {
+                    total = 1;
+                }
+                let elapsed = start.elapsed();
+                println!();
+                println!("Nodes searched: {}", total);
+                println!(
+                    "Time: {} ms, {} nps",
+                    elapsed.as_millis(),
+                    (total as f32 / elapsed.as_secs_f32()) as u64
+                );
+            }
+            UciCommand::Calibrate(games, reference) => calibrate::calibrate(games, reference),
+            UciCommand::Static => println!("{}", self.engine.lock().unwrap().static_eval()),
+            UciCommand::Display => {
+                let engine = &mut *self.engine.lock().unwrap();
+                let static_eval = engine.static_eval();
+                println!(
+                    "{}",
+                    display::display(engine.position(), engine.chess960(), static_eval)
+                );
             }
         }
         true
     }
 
-    fn go(&mut self, commands: Vec<TimeManagementInfo>) {
-        self.forced = false;
-        self.time_manager
-            .initiate(self.bm_runner.lock().unwrap().get_board(), &commands);
-        let bm_runner = self.bm_runner.clone();
-        let chess960 = self.chess960;
-
-        let req = GoReq {
-            bm_runner,
-            chess960,
+    /// Applies options set during a search once it has ended
+    /// - Returns without applying them if the search is still running, unless `wait` is set
+    fn apply_pending_options(&mut self, wait: bool) {
+        if self.pending_options.is_empty() {
+            return;
+        }
+        let mut engine = match wait {
+            true => self.engine.lock().unwrap(),
+            false => match self.engine.try_lock() {
+                Ok(engine) => engine,
+                Err(_) => return,
+            },
         };
-        self.sender.send(ThreadReq::Go(req)).unwrap();
+        for (name, value) in self.pending_options.drain(..) {
+            for message in self.options.apply(&mut engine, name, value) {
+                println!("info string {}", message);
+            }
+        }
+    }
+
+    /// Starts the search on the worker thread, which prints `bestmove` once it's done
+    /// - Prints a null move right away if none of the search moves are legal
+    fn go(&mut self, limits: &SearchLimits) {
+        self.apply_pending_options(true);
+        match self.engine.lock().unwrap().start_search(limits) {
+            Ok(illegal) => {
+                for err in illegal {
+                    println!("info string {}, ignoring it in searchmoves", err);
+                }
+            }
+            Err(err) => {
+                println!("info string {}", err);
+                println!("bestmove 0000");
+                return;
+            }
+        }
+        self.sender.send(ThreadReq::Go).unwrap();
     }
 
     fn exit(&mut self) {
-        self.time_manager.abort_now();
+        self.stop_handle.stop();
         self.sender.send(ThreadReq::Quit).unwrap();
     }
 }
@@ -226,7 +344,7 @@ pub fn convert_move_to_uci(make_move: &mut Move, board: &Board, chess960: bool)
     }
 }
 
-fn convert_move(make_move: &mut Move, board: &Board, chess960: bool) {
+pub(crate) fn convert_move(make_move: &mut Move, board: &Board, chess960: bool) {
     let convert_castle = !chess960
         && board.piece_on(make_move.from) == Some(Piece::King)
         && make_move.from.file() == File::E
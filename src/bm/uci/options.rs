@@ -0,0 +1,270 @@
+use std::fmt::Display;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::bm::bm_runner::skill::MAX_LEVEL;
+use crate::bm::bm_util::position::Aggression;
+use crate::bm::engine::Engine;
+
+use super::command::UciError;
+
+#[derive(Debug, Clone, Copy)]
+pub enum OptionKind {
+    Spin { default: i64, min: i64, max: i64 },
+    Check { default: bool },
+    String { default: &'static str },
+    Button,
+}
+
+/// Option advertised on `uci` and accepted by `setoption`
+#[derive(Debug, Clone, Copy)]
+pub struct UciOption {
+    pub name: &'static str,
+    pub kind: OptionKind,
+}
+
+/// Validated option value, matching the [OptionKind] of its option
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OptionValue {
+    Spin(i64),
+    Check(bool),
+    String(String),
+    Button,
+}
+
+const fn spin(name: &'static str, default: i64, min: i64, max: i64) -> UciOption {
+    UciOption {
+        name,
+        kind: OptionKind::Spin { default, min, max },
+    }
+}
+
+const fn check(name: &'static str, default: bool) -> UciOption {
+    UciOption {
+        name,
+        kind: OptionKind::Check { default },
+    }
+}
+
+const fn string(name: &'static str, default: &'static str) -> UciOption {
+    UciOption {
+        name,
+        kind: OptionKind::String { default },
+    }
+}
+
+const fn button(name: &'static str) -> UciOption {
+    UciOption {
+        name,
+        kind: OptionKind::Button,
+    }
+}
+
+pub const OPTIONS: &[UciOption] = &[
+    spin("Hash", 16, 1, 65536),
+    check("Rehash On Resize", false),
+    button("Clear Hash"),
+    spin("Threads", 1, 1, 65535),
+    spin("Move Overhead", 10, 0, 5000),
+    spin("nodestime", 0, 0, 10000),
+    check("Deterministic", false),
+    spin("Skill Level", MAX_LEVEL as i64, 0, MAX_LEVEL as i64),
+    spin("Contempt", 0, -100, 100),
+    check("UCI_AnalyseMode", false),
+    spin("Aggression", 2, 0, 10),
+    spin("Aggression Limit", 200, 0, 1000),
+    check("UCI_ShowWDL", false),
+    check("UCI_Chess960", false),
+    spin("MultiPV", 1, 1, 256),
+    check("Ponder", false),
+    string("EvalFile", "<empty>"),
+    check("KPK KRK Tablebase", false),
+];
+
+/// Looks up an option, names are case insensitive
+pub fn find(name: &str) -> Result<&'static UciOption, UciError> {
+    OPTIONS
+        .iter()
+        .find(|option| option.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| UciError::UnknownOption(name.to_string()))
+}
+
+impl UciOption {
+    /// Validates a `setoption` value against the option type
+    /// - Buttons ignore the value
+    pub fn parse(&self, value: Option<&str>) -> Result<OptionValue, UciError> {
+        let invalid = |value: &str| UciError::InvalidOptionValue {
+            name: self.name,
+            value: value.to_string(),
+        };
+        let value = match (self.kind, value) {
+            (OptionKind::Button, _) => return Ok(OptionValue::Button),
+            (_, Some(value)) => value,
+            (_, None) => return Err(UciError::MissingOptionValue(self.name.to_string())),
+        };
+        match self.kind {
+            OptionKind::Spin { min, max, .. } => {
+                let spin = value.parse::<i64>().map_err(|_| invalid(value))?;
+                if !(min..=max).contains(&spin) {
+                    return Err(UciError::OutOfRange {
+                        name: self.name,
+                        value: spin,
+                        min,
+                        max,
+                    });
+                }
+                Ok(OptionValue::Spin(spin))
+            }
+            OptionKind::Check { .. } => match value.to_ascii_lowercase().as_str() {
+                "true" => Ok(OptionValue::Check(true)),
+                "false" => Ok(OptionValue::Check(false)),
+                _ => Err(invalid(value)),
+            },
+            OptionKind::String { .. } => Ok(OptionValue::String(value.to_string())),
+            OptionKind::Button => unreachable!(),
+        }
+    }
+}
+
+impl Display for UciOption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "option name {} type ", self.name)?;
+        match self.kind {
+            OptionKind::Spin { default, min, max } => {
+                write!(f, "spin default {} min {} max {}", default, min, max)
+            }
+            OptionKind::Check { default } => write!(f, "check default {}", default),
+            OptionKind::String { default } => write!(f, "string default {}", default),
+            OptionKind::Button => write!(f, "button"),
+        }
+    }
+}
+
+/// Option values that are combined before they're passed to the [Engine]
+/// - Shared by every protocol adapter so options behave the same everywhere
+#[derive(Debug, Clone)]
+pub struct EngineOptions {
+    chess960: bool,
+    rehash: bool,
+    aggression: Aggression,
+    threads: u16,
+    deterministic: bool,
+}
+
+impl Default for EngineOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EngineOptions {
+    pub fn new() -> Self {
+        Self {
+            chess960: false,
+            rehash: false,
+            aggression: Aggression::default(),
+            threads: 1,
+            deterministic: false,
+        }
+    }
+
+    /// Positions and moves are parsed in Chess960 notation
+    pub fn chess960(&self) -> bool {
+        self.chess960
+    }
+
+    /// Applies a value validated by [UciOption::parse], returning messages for the user
+    pub fn apply(&mut self, engine: &mut Engine, name: &str, value: OptionValue) -> Vec<String> {
+        let mut messages = vec![];
+        match (name, value) {
+            ("Hash", OptionValue::Spin(hash)) => engine.set_hash(hash as usize, self.rehash),
+            ("Rehash On Resize", OptionValue::Check(rehash)) => self.rehash = rehash,
+            ("Clear Hash", OptionValue::Button) => engine.clear_hash(),
+            ("Threads", OptionValue::Spin(threads)) => {
+                self.threads = threads as u16;
+                engine.set_threads(self.threads);
+            }
+            ("Move Overhead", OptionValue::Spin(overhead)) => {
+                engine.set_move_overhead(Duration::from_millis(overhead as u64))
+            }
+            ("Skill Level", OptionValue::Spin(level)) => engine.set_skill_level(level as u8),
+            ("Contempt", OptionValue::Spin(contempt)) => engine.set_contempt(contempt as i16),
+            ("UCI_AnalyseMode", OptionValue::Check(analyse_mode)) => {
+                engine.set_analyse_mode(analyse_mode)
+            }
+            ("Aggression", OptionValue::Spin(scale)) => {
+                self.aggression.scale = scale as i16;
+                engine.set_aggression(self.aggression);
+            }
+            ("Aggression Limit", OptionValue::Spin(limit)) => {
+                self.aggression.limit = limit as i16;
+                engine.set_aggression(self.aggression);
+            }
+            ("Deterministic", OptionValue::Check(deterministic)) => {
+                self.deterministic = deterministic;
+                engine.set_deterministic(deterministic);
+            }
+            ("nodestime", OptionValue::Spin(nodes_time)) => {
+                engine.set_nodes_time(nodes_time as u32)
+            }
+            ("UCI_Chess960", OptionValue::Check(chess960)) => {
+                self.chess960 = chess960;
+                engine.set_chess960(chess960);
+            }
+            ("UCI_ShowWDL", OptionValue::Check(show_wdl)) => engine.set_show_wdl(show_wdl),
+            ("MultiPV", OptionValue::Spin(multi_pv)) => engine.set_multi_pv(multi_pv as usize),
+            ("EvalFile", OptionValue::String(path)) => match path.as_str() {
+                "" | "<empty>" => engine.set_network(None).unwrap(),
+                path => match engine.set_network(Some(Path::new(path))) {
+                    Ok(()) => messages.push(format!("loaded network {}", path)),
+                    Err(err) => {
+                        messages.push(format!(
+                            "failed to load network {}: {}, using embedded network",
+                            path, err
+                        ));
+                        engine.set_network(None).unwrap();
+                    }
+                },
+            },
+            ("KPK KRK Tablebase", OptionValue::Check(enabled)) => engine.set_tablebase(enabled),
+            _ => {}
+        }
+        if matches!(name, "Threads" | "Deterministic") && self.deterministic && self.threads > 1 {
+            messages.push(format!(
+                "Deterministic ignores Threads, searching with 1 thread instead of {}",
+                self.threads
+            ));
+        }
+        messages
+    }
+}
+
+#[test]
+fn option_values() {
+    let hash = find("hash").unwrap();
+    assert_eq!(hash.parse(Some("64")), Ok(OptionValue::Spin(64)));
+    assert!(matches!(
+        hash.parse(Some("0")),
+        Err(UciError::OutOfRange { .. })
+    ));
+    assert!(hash.parse(Some("x")).is_err());
+    assert!(hash.parse(None).is_err());
+
+    let show_wdl = find("UCI_ShowWDL").unwrap();
+    assert_eq!(show_wdl.parse(Some("True")), Ok(OptionValue::Check(true)));
+    assert!(show_wdl.parse(Some("yes")).is_err());
+
+    assert_eq!(
+        find("EvalFile").unwrap().parse(Some("my nets/a b.bin")),
+        Ok(OptionValue::String("my nets/a b.bin".to_string()))
+    );
+    assert_eq!(
+        find("clear hash").unwrap().parse(None),
+        Ok(OptionValue::Button)
+    );
+    assert!(find("Unknown").is_err());
+    assert_eq!(
+        hash.to_string(),
+        "option name Hash type spin default 16 min 1 max 65536"
+    );
+}
@@ -0,0 +1,130 @@
+use cozy_chess::Move;
+
+use crate::bm::bm_util::position::Position;
+
+use super::convert_move_to_uci;
+
+fn legal_moves(position: &Position) -> Vec<Move> {
+    let mut moves = vec![];
+    position.board().generate_moves(|piece_moves| {
+        moves.extend(piece_moves);
+        false
+    });
+    moves
+}
+
+/// Counts leaf nodes at the given depth
+/// - Moves are made through [Position] so incremental updates are exercised
+pub fn perft(position: &mut Position, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = legal_moves(position);
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+    let mut nodes = 0;
+    for make_move in moves {
+        position.make_move(make_move);
+        nodes += perft(position, depth - 1);
+        position.unmake_move();
+    }
+    nodes
+}
+
+/// Returns the [perft] node count of every root move, with moves in UCI notation
+pub fn divide(position: &mut Position, depth: u32, chess960: bool) -> Vec<(Move, u64)> {
+    if depth == 0 {
+        return vec![];
+    }
+    let mut divided = vec![];
+    for make_move in legal_moves(position) {
+        let mut uci_move = make_move;
+        convert_move_to_uci(&mut uci_move, position.board(), chess960);
+        position.make_move(make_move);
+        divided.push((uci_move, perft(position, depth - 1)));
+        position.unmake_move();
+    }
+    divided
+}
+
+/// Same as [perft], verifying incremental state and UCI move conversion at every node
+#[cfg(test)]
+fn checked_perft(position: &mut Position, depth: u32, chess960: bool) -> u64 {
+    position.assert_consistent();
+    if depth == 0 {
+        return 1;
+    }
+    let mut nodes = 0;
+    for make_move in legal_moves(position) {
+        let mut uci_move = make_move;
+        convert_move_to_uci(&mut uci_move, position.board(), chess960);
+        let mut converted = uci_move;
+        super::convert_move(&mut converted, position.board(), chess960);
+        assert_eq!(converted, make_move, "{} in {}", uci_move, position.board());
+
+        position.make_move(make_move);
+        nodes += checked_perft(position, depth - 1, chess960);
+        position.unmake_move();
+    }
+    position.assert_consistent();
+    nodes
+}
+
+#[test]
+fn perft_positions() {
+    use cozy_chess::Board;
+
+    const STANDARD: &[(&str, u32, u64)] = &[
+        (
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            3,
+            8902,
+        ),
+        (
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            2,
+            2039,
+        ),
+        ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 3, 2812),
+        (
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            2,
+            264,
+        ),
+        (
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            2,
+            1486,
+        ),
+    ];
+    const CHESS_960: &[(&str, u32, u64)] = &[
+        (
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+            2,
+            528,
+        ),
+        (
+            "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+            2,
+            807,
+        ),
+    ];
+    for (positions, chess960) in [(STANDARD, false), (CHESS_960, true)] {
+        for &(fen, depth, expected) in positions {
+            let board = Board::from_fen(fen, chess960).unwrap();
+            let mut position = Position::new(board);
+            assert_eq!(
+                checked_perft(&mut position, depth, chess960),
+                expected,
+                "{}",
+                fen
+            );
+            let divided: u64 = divide(&mut position, depth, chess960)
+                .into_iter()
+                .map(|(_, nodes)| nodes)
+                .sum();
+            assert_eq!(divided, expected, "{}", fen);
+        }
+    }
+}
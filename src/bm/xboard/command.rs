@@ -0,0 +1,280 @@
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::Duration;
+
+use cozy_chess::{Board, Move};
+
+/// Move sent by the GUI, castling is sent in SAN in Chess960 games
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UserMove {
+    Coordinate(Move),
+    ShortCastle,
+    LongCastle,
+}
+
+pub enum XBoardCommand {
+    XBoard,
+    ProtoVer(u32),
+    New,
+    Force,
+    Go,
+    UserMove(UserMove),
+    /// Moves per time control session, 0 for the whole game, base time and increment
+    Level(u32, Duration, Duration),
+    /// Exact time for every move
+    MoveTime(Duration),
+    Depth(u32),
+    /// Remaining time on the engine's clock
+    Time(Duration),
+    /// Remaining time on the opponent's clock
+    OpponentTime(Duration),
+    Post(bool),
+    Analyze,
+    Exit,
+    SetBoard(Board),
+    /// Chess960 is enabled by `fischerandom` and disabled by `normal`
+    Variant(bool),
+    Result,
+    MoveNow,
+    Ping(u32),
+    Empty,
+    Quit,
+}
+
+/// Reasons a command can't be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum XBoardError {
+    UnknownCommand(String),
+    /// Command is missing its value
+    MissingValue(&'static str),
+    /// Value of a command can't be parsed
+    InvalidValue(&'static str, String),
+    InvalidFen(String),
+    InvalidMove(String),
+    UnsupportedVariant(String),
+}
+
+impl Display for XBoardError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            XBoardError::UnknownCommand(command) => write!(f, "unknown command {}", command),
+            XBoardError::MissingValue(key) => write!(f, "missing value for {}", key),
+            XBoardError::InvalidValue(key, value) => {
+                write!(f, "invalid value {} for {}", value, key)
+            }
+            XBoardError::InvalidFen(fen) => write!(f, "invalid fen {}", fen),
+            XBoardError::InvalidMove(make_move) => write!(f, "invalid move {}", make_move),
+            XBoardError::UnsupportedVariant(variant) => {
+                write!(f, "unsupported variant {}", variant)
+            }
+        }
+    }
+}
+
+/// Parses the token following a command
+fn parse_value<'a, T: FromStr>(
+    split: &mut impl Iterator<Item = &'a str>,
+    key: &'static str,
+) -> Result<T, XBoardError> {
+    let value = split.next().ok_or(XBoardError::MissingValue(key))?;
+    value
+        .parse()
+        .map_err(|_| XBoardError::InvalidValue(key, value.to_string()))
+}
+
+/// Parses a time in centiseconds, negative times are treated as zero
+fn parse_centis<'a>(
+    split: &mut impl Iterator<Item = &'a str>,
+    key: &'static str,
+) -> Result<Duration, XBoardError> {
+    let centis = parse_value::<i64>(split, key)?;
+    Ok(Duration::from_millis(centis.max(0) as u64 * 10))
+}
+
+/// Parses a possibly fractional number of seconds
+fn parse_seconds<'a>(
+    split: &mut impl Iterator<Item = &'a str>,
+    key: &'static str,
+) -> Result<Duration, XBoardError> {
+    let value = split.next().ok_or(XBoardError::MissingValue(key))?;
+    value
+        .parse::<f64>()
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| XBoardError::InvalidValue(key, value.to_string()))
+}
+
+/// Parses a base time given in minutes or as `minutes:seconds`
+fn parse_base<'a>(split: &mut impl Iterator<Item = &'a str>) -> Result<Duration, XBoardError> {
+    let value = split.next().ok_or(XBoardError::MissingValue("level"))?;
+    let invalid = || XBoardError::InvalidValue("level", value.to_string());
+    let (minutes, seconds) = value.split_once(':').unwrap_or((value, "0"));
+    let minutes = minutes.parse::<u64>().map_err(|_| invalid())?;
+    let seconds = seconds.parse::<u64>().map_err(|_| invalid())?;
+    Ok(Duration::from_secs(minutes * 60 + seconds))
+}
+
+/// Converts KQkq castling rights of X-FEN to the rook files of Shredder-FEN
+/// - Each right refers to the outermost rook on its side of the king
+fn shredder_fen(fen: &str) -> Option<String> {
+    let mut fields = fen.split_ascii_whitespace().collect::<Vec<_>>();
+    let ranks = fields.first()?.split('/').collect::<Vec<_>>();
+    let expand = |rank: &str| {
+        let mut squares = vec![];
+        for piece in rank.chars() {
+            match piece.to_digit(10) {
+                Some(empty) => squares.extend((0..empty).map(|_| '.')),
+                None => squares.push(piece),
+            }
+        }
+        squares
+    };
+    let (black_rank, white_rank) = (expand(ranks.first()?), expand(ranks.last()?));
+    let rook_file = |rank: &[char], king: char, rook: char, short: bool| {
+        let king = rank.iter().position(|&piece| piece == king)?;
+        let file = match short {
+            true => rank.iter().rposition(|&piece| piece == rook)?,
+            false => rank.iter().position(|&piece| piece == rook)?,
+        };
+        Some(char::from(b'a' + file as u8)).filter(|_| (file > king) == short)
+    };
+    let rights = fields
+        .get(2)?
+        .chars()
+        .map(|right| match right {
+            'K' | 'Q' => {
+                rook_file(&white_rank, 'K', 'R', right == 'K').map(|file| file.to_ascii_uppercase())
+            }
+            'k' | 'q' => rook_file(&black_rank, 'k', 'r', right == 'k'),
+            _ => Some(right),
+        })
+        .collect::<Option<String>>()?;
+    fields[2] = &rights;
+    Some(fields.join(" "))
+}
+
+fn parse_move(token: &str) -> Result<UserMove, XBoardError> {
+    match token {
+        "O-O" | "0-0" => Ok(UserMove::ShortCastle),
+        "O-O-O" | "0-0-0" => Ok(UserMove::LongCastle),
+        _ => token
+            .parse()
+            .map(UserMove::Coordinate)
+            .map_err(|_| XBoardError::InvalidMove(token.to_string())),
+    }
+}
+
+impl XBoardCommand {
+    pub fn parse(input: &str, chess960: bool) -> Result<Self, XBoardError> {
+        let mut split = input.split_ascii_whitespace();
+        let token = match split.next() {
+            Some(string) => string,
+            None => return Ok(XBoardCommand::Empty),
+        };
+        Ok(match token {
+            "xboard" => XBoardCommand::XBoard,
+            "protover" => XBoardCommand::ProtoVer(parse_value(&mut split, "protover")?),
+            "new" => XBoardCommand::New,
+            "force" => XBoardCommand::Force,
+            "go" => XBoardCommand::Go,
+            "usermove" => {
+                let make_move = split.next().ok_or(XBoardError::MissingValue("usermove"))?;
+                XBoardCommand::UserMove(parse_move(make_move)?)
+            }
+            "level" => {
+                let moves_per_session = parse_value(&mut split, "level")?;
+                let base = parse_base(&mut split)?;
+                let inc = parse_seconds(&mut split, "level")?;
+                XBoardCommand::Level(moves_per_session, base, inc)
+            }
+            "st" => XBoardCommand::MoveTime(parse_seconds(&mut split, "st")?),
+            "sd" => XBoardCommand::Depth(parse_value(&mut split, "sd")?),
+            "time" => XBoardCommand::Time(parse_centis(&mut split, "time")?),
+            "otim" => XBoardCommand::OpponentTime(parse_centis(&mut split, "otim")?),
+            "post" => XBoardCommand::Post(true),
+            "nopost" => XBoardCommand::Post(false),
+            "analyze" => XBoardCommand::Analyze,
+            "exit" => XBoardCommand::Exit,
+            "setboard" => {
+                let fen = split.collect::<Vec<_>>().join(" ");
+                let board = match Board::from_fen(&fen, chess960) {
+                    Ok(board) => board,
+                    // GUIs send X-FEN in Chess960 games
+                    Err(_) if chess960 => shredder_fen(&fen)
+                        .and_then(|fen| Board::from_fen(&fen, true).ok())
+                        .ok_or(XBoardError::InvalidFen(fen))?,
+                    Err(_) => return Err(XBoardError::InvalidFen(fen)),
+                };
+                XBoardCommand::SetBoard(board)
+            }
+            "variant" => match split.next() {
+                Some("normal") => XBoardCommand::Variant(false),
+                Some("fischerandom") => XBoardCommand::Variant(true),
+                Some(variant) => return Err(XBoardError::UnsupportedVariant(variant.to_string())),
+                None => return Err(XBoardError::MissingValue("variant")),
+            },
+            "result" => XBoardCommand::Result,
+            "?" => XBoardCommand::MoveNow,
+            "ping" => XBoardCommand::Ping(parse_value(&mut split, "ping")?),
+            "quit" => XBoardCommand::Quit,
+            "accepted" | "rejected" | "random" | "computer" | "name" | "rating" | "ics"
+            | "hard" | "easy" | "." => XBoardCommand::Empty,
+            // Protocol version 1 sends moves without `usermove`
+            _ => match parse_move(token) {
+                Ok(make_move) => XBoardCommand::UserMove(make_move),
+                Err(_) => return Err(XBoardError::UnknownCommand(token.to_string())),
+            },
+        })
+    }
+}
+
+#[test]
+fn xboard_commands() {
+    let parse = |input: &str| XBoardCommand::parse(input, false);
+
+    assert!(matches!(
+        parse("level 40 0:30 0.5"),
+        Ok(XBoardCommand::Level(40, base, inc))
+            if base == Duration::from_secs(30) && inc == Duration::from_millis(500)
+    ));
+    assert!(matches!(
+        parse("level 0 5 0"),
+        Ok(XBoardCommand::Level(0, base, inc)) if base == Duration::from_secs(300) && inc.is_zero()
+    ));
+    assert!(matches!(
+        parse("time -10"),
+        Ok(XBoardCommand::Time(time)) if time.is_zero()
+    ));
+    assert!(matches!(
+        parse("usermove O-O-O"),
+        Ok(XBoardCommand::UserMove(UserMove::LongCastle))
+    ));
+    assert!(matches!(
+        parse("e2e4"),
+        Ok(XBoardCommand::UserMove(UserMove::Coordinate(_)))
+    ));
+
+    assert!(matches!(
+        XBoardCommand::parse("setboard rk2r3/8/8/8/8/8/8/RK2R3 w KQkq - 0 1", true),
+        Ok(XBoardCommand::SetBoard(board))
+            if board.castle_rights(cozy_chess::Color::White).short == Some(cozy_chess::File::E)
+    ));
+
+    assert_eq!(
+        parse("level 40 x 0").err(),
+        Some(XBoardError::InvalidValue("level", "x".to_string()))
+    );
+    assert_eq!(parse("sd").err(), Some(XBoardError::MissingValue("sd")));
+    assert_eq!(
+        parse("variant crazyhouse").err(),
+        Some(XBoardError::UnsupportedVariant("crazyhouse".to_string()))
+    );
+    assert_eq!(
+        parse("usermove e9e4").err(),
+        Some(XBoardError::InvalidMove("e9e4".to_string()))
+    );
+    assert_eq!(
+        parse("foo").err(),
+        Some(XBoardError::UnknownCommand("foo".to_string()))
+    );
+}
@@ -0,0 +1,412 @@
+//! Chess Engine Communication Protocol, selected when the first command is `xboard`
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use cozy_chess::{Board, Color, GameStatus, Move, Square};
+
+use crate::bm::bm_runner::config::{Score, SearchObserver, SearchUpdate};
+use crate::bm::engine::{Engine, IllegalMove, SearchLimits, StopHandle};
+use crate::bm::uci::VERSION;
+
+mod command;
+
+use command::{UserMove, XBoardCommand, XBoardError};
+
+enum ThreadReq {
+    /// Search and play the best move
+    Think {
+        post: bool,
+    },
+    Analyze,
+}
+
+/// Prints search progress as thinking output, the move is played by the worker thread
+struct XBoardOutput {
+    post: bool,
+}
+
+impl SearchObserver for XBoardOutput {
+    fn update(&mut self, info: &SearchUpdate) {
+        if !self.post || info.multi_pv != 1 {
+            return;
+        }
+        let score = match info.score {
+            Score::Cp(cp) => cp as i32,
+            Score::Mate(mate_in) if mate_in > 0 => 100000 + mate_in as i32,
+            Score::Mate(mate_in) => -100000 + mate_in as i32,
+        };
+        let mut output = format!(
+            "{} {} {} {}",
+            info.depth,
+            score,
+            info.time.as_millis() / 10,
+            info.nodes
+        );
+        for make_move in &info.pv {
+            write!(&mut output, " {}", make_move).unwrap();
+        }
+        println!("{}", output);
+    }
+}
+
+/// Time controls set by `level`, `st`, `sd`, `time` and `otim`
+#[derive(Debug, Clone, Default)]
+struct TimeControl {
+    /// Moves until the clocks are refilled, 0 if the base time is for the whole game
+    moves_per_session: u32,
+    inc: Duration,
+    move_time: Option<Duration>,
+    depth: Option<u32>,
+    time: Option<Duration>,
+    opponent_time: Option<Duration>,
+}
+
+impl TimeControl {
+    /// Limits of a search where the engine plays the side to move
+    fn limits(&self, board: &Board) -> SearchLimits {
+        let mut limits = SearchLimits {
+            depth: self.depth,
+            ..SearchLimits::default()
+        };
+        if let Some(move_time) = self.move_time {
+            limits.move_time = Some(move_time);
+            return limits;
+        }
+        let (time, opponent_time) = match board.side_to_move() {
+            Color::White => (&mut limits.wtime, &mut limits.btime),
+            Color::Black => (&mut limits.btime, &mut limits.wtime),
+        };
+        *time = self.time;
+        *opponent_time = self.opponent_time;
+        if limits.wtime.is_some() || limits.btime.is_some() {
+            limits.winc = Some(self.inc);
+            limits.binc = Some(self.inc);
+        }
+        if self.moves_per_session > 0 {
+            let played = (board.fullmove_number() as u32 - 1) % self.moves_per_session;
+            limits.moves_to_go = Some(self.moves_per_session - played);
+        }
+        limits
+    }
+}
+
+/// Castling is sent as king captures rook in Chess960 games, GUIs expect SAN for it
+fn format_move(board: &Board, make_move: Move, chess960: bool) -> String {
+    if chess960 && board.color_on(make_move.from) == board.color_on(make_move.to) {
+        match make_move.to.file() > make_move.from.file() {
+            true => "O-O".to_string(),
+            false => "O-O-O".to_string(),
+        }
+    } else {
+        make_move.to_string()
+    }
+}
+
+/// Converts castling in SAN to king captures rook
+fn resolve_move(board: &Board, make_move: UserMove) -> Result<Move, XBoardError> {
+    let color = board.side_to_move();
+    let rights = board.castle_rights(color);
+    let rook = match make_move {
+        UserMove::Coordinate(make_move) => return Ok(make_move),
+        UserMove::ShortCastle => rights.short,
+        UserMove::LongCastle => rights.long,
+    };
+    let king = board.king(color);
+    match rook {
+        Some(rook) => Ok(Move {
+            from: king,
+            to: Square::new(rook, king.rank()),
+            promotion: None,
+        }),
+        None => Err(XBoardError::InvalidMove(match make_move {
+            UserMove::ShortCastle => "O-O".to_string(),
+            _ => "O-O-O".to_string(),
+        })),
+    }
+}
+
+/// Game result line for positions without legal moves
+fn game_result(board: &Board) -> Option<&'static str> {
+    match (board.status(), board.side_to_move()) {
+        (GameStatus::Ongoing, _) => None,
+        (GameStatus::Drawn, _) => Some("1/2-1/2 {Draw}"),
+        (GameStatus::Won, Color::White) => Some("0-1 {Black mates}"),
+        (GameStatus::Won, Color::Black) => Some("1-0 {White mates}"),
+    }
+}
+
+fn print_error(input: &str, err: XBoardError) {
+    match err {
+        XBoardError::InvalidMove(make_move) => println!("Illegal move: {}", make_move),
+        XBoardError::InvalidFen(_) => println!("tellusererror Illegal position"),
+        err => println!("Error ({}): {}", err, input.trim()),
+    }
+}
+
+pub struct XBoardAdapter {
+    engine: Arc<Mutex<Engine>>,
+    stop_handle: StopHandle,
+
+    sender: Sender<ThreadReq>,
+    /// Signals that the worker thread finished a search
+    done: Receiver<()>,
+    /// The worker thread drops the result of a cancelled search instead of playing it
+    cancelled: Arc<AtomicBool>,
+    searching: bool,
+    /// Engine plays neither side
+    force: bool,
+    analyzing: bool,
+    post: bool,
+    /// The engine is locked while searching, so the variant is kept for parsing
+    chess960: bool,
+    time_control: TimeControl,
+}
+
+impl Default for XBoardAdapter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl XBoardAdapter {
+    pub fn new() -> Self {
+        let engine = Engine::new();
+        let stop_handle = engine.stop_handle();
+        let engine = Arc::new(Mutex::new(engine));
+
+        let (tx, rx): (Sender<ThreadReq>, Receiver<ThreadReq>) = mpsc::channel();
+        let (done_tx, done_rx) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let thread_engine = engine.clone();
+        let thread_cancelled = cancelled.clone();
+        std::thread::spawn(move || {
+            // Ends once the adapter is dropped
+            for req in rx {
+                let engine = &mut *thread_engine.lock().unwrap();
+                match req {
+                    ThreadReq::Think { post } => {
+                        let result = engine.run_search(XBoardOutput { post });
+                        // The game is already over if there is no move to play
+                        if let (Some(best_move), false) =
+                            (result.best_move, thread_cancelled.load(Ordering::SeqCst))
+                        {
+                            let make_move =
+                                format_move(engine.board(), best_move, engine.chess960());
+                            engine.play_move(best_move).unwrap();
+                            println!("move {}", make_move);
+                            if let Some(result) = game_result(engine.board()) {
+                                println!("{}", result);
+                            }
+                        }
+                    }
+                    ThreadReq::Analyze => {
+                        engine.run_search(XBoardOutput { post: true });
+                    }
+                }
+                if done_tx.send(()).is_err() {
+                    return;
+                }
+            }
+        });
+        Self {
+            engine,
+            stop_handle,
+            sender: tx,
+            done: done_rx,
+            cancelled,
+            searching: false,
+            force: false,
+            analyzing: false,
+            post: false,
+            chess960: false,
+            time_control: TimeControl::default(),
+        }
+    }
+
+    /// Stops the running search without playing its move and waits for it to end
+    fn cancel_search(&mut self) {
+        if self.searching {
+            self.cancelled.store(true, Ordering::SeqCst);
+            self.stop_handle.stop();
+            self.done.recv().unwrap();
+            self.searching = false;
+        }
+    }
+
+    /// Thinks on the side to move or restarts the analysis after the position changed
+    fn search(&mut self) {
+        let engine = &mut *self.engine.lock().unwrap();
+        if let Some(result) = game_result(engine.board()) {
+            if !self.analyzing {
+                println!("{}", result);
+            }
+            return;
+        }
+        // Without search moves every legal move is searched, so preparing can't fail
+        let req = if self.analyzing {
+            engine
+                .start_search(&SearchLimits {
+                    infinite: true,
+                    ..SearchLimits::default()
+                })
+                .unwrap();
+            ThreadReq::Analyze
+        } else if !self.force {
+            engine
+                .start_search(&self.time_control.limits(engine.board()))
+                .unwrap();
+            ThreadReq::Think { post: self.post }
+        } else {
+            return;
+        };
+        self.cancelled.store(false, Ordering::SeqCst);
+        self.sender.send(req).unwrap();
+        self.searching = true;
+    }
+
+    pub fn input(&mut self, input: &str) -> bool {
+        let command = match XBoardCommand::parse(input, self.chess960) {
+            Ok(command) => command,
+            Err(err) => {
+                print_error(input, err);
+                return true;
+            }
+        };
+        // Searches only end by themselves when thinking
+        if self.searching && self.done.try_recv().is_ok() {
+            self.searching = false;
+        }
+        match command {
+            XBoardCommand::XBoard => println!(),
+            // Features were introduced in version 2
+            XBoardCommand::ProtoVer(version) if version < 2 => {}
+            XBoardCommand::ProtoVer(_) => {
+                println!(
+                    "feature myname=\"Black Marlin {}\" ping=1 setboard=1 usermove=1 analyze=1 \
+                     colors=0 sigint=0 sigterm=0 reuse=1 variants=\"normal,fischerandom\" done=1",
+                    VERSION
+                );
+            }
+            XBoardCommand::New => {
+                self.cancel_search();
+                let engine = &mut *self.engine.lock().unwrap();
+                engine.new_game();
+                engine.set_chess960(false);
+                self.chess960 = false;
+                self.force = false;
+                self.time_control.depth = None;
+            }
+            XBoardCommand::Force => {
+                self.cancel_search();
+                self.force = true;
+            }
+            XBoardCommand::Go => {
+                self.cancel_search();
+                self.force = false;
+                self.search();
+            }
+            XBoardCommand::UserMove(make_move) => {
+                self.cancel_search();
+                let played = {
+                    let engine = &mut *self.engine.lock().unwrap();
+                    resolve_move(engine.board(), make_move).and_then(|resolved| {
+                        engine
+                            .play_move(resolved)
+                            .map_err(|IllegalMove(make_move)| {
+                                XBoardError::InvalidMove(make_move.to_string())
+                            })
+                    })
+                };
+                match played {
+                    Ok(()) => self.search(),
+                    Err(err) => print_error(input, err),
+                }
+            }
+            XBoardCommand::Level(moves_per_session, base, inc) => {
+                self.time_control.moves_per_session = moves_per_session;
+                self.time_control.inc = inc;
+                self.time_control.move_time = None;
+                self.time_control.time = Some(base);
+                self.time_control.opponent_time = Some(base);
+            }
+            XBoardCommand::MoveTime(move_time) => self.time_control.move_time = Some(move_time),
+            XBoardCommand::Depth(depth) => self.time_control.depth = Some(depth),
+            XBoardCommand::Time(time) => self.time_control.time = Some(time),
+            XBoardCommand::OpponentTime(time) => self.time_control.opponent_time = Some(time),
+            XBoardCommand::Post(post) => self.post = post,
+            XBoardCommand::Analyze => {
+                self.cancel_search();
+                self.analyzing = true;
+                self.search();
+            }
+            XBoardCommand::Exit => {
+                self.cancel_search();
+                self.analyzing = false;
+            }
+            XBoardCommand::SetBoard(board) => {
+                self.cancel_search();
+                self.engine
+                    .lock()
+                    .unwrap()
+                    .set_position(board, &[])
+                    .unwrap();
+                if self.analyzing {
+                    self.search();
+                }
+            }
+            XBoardCommand::Variant(chess960) => {
+                self.cancel_search();
+                let engine = &mut *self.engine.lock().unwrap();
+                engine.set_chess960(chess960);
+                self.chess960 = chess960;
+            }
+            XBoardCommand::Result => self.cancel_search(),
+            XBoardCommand::MoveNow => self.stop_handle.stop(),
+            XBoardCommand::Ping(ping) => println!("pong {}", ping),
+            XBoardCommand::Empty => {}
+            XBoardCommand::Quit => {
+                self.cancel_search();
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[test]
+fn time_control_limits() {
+    let time_control = TimeControl {
+        moves_per_session: 40,
+        inc: Duration::from_secs(1),
+        time: Some(Duration::from_secs(60)),
+        opponent_time: Some(Duration::from_secs(30)),
+        ..TimeControl::default()
+    };
+    let board: Board = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 41"
+        .parse()
+        .unwrap();
+    let limits = time_control.limits(&board);
+    assert_eq!(limits.btime, Some(Duration::from_secs(60)));
+    assert_eq!(limits.wtime, Some(Duration::from_secs(30)));
+    assert_eq!(limits.binc, Some(Duration::from_secs(1)));
+    assert_eq!(limits.moves_to_go, Some(40));
+
+    let time_control = TimeControl {
+        move_time: Some(Duration::from_secs(5)),
+        depth: Some(8),
+        ..time_control
+    };
+    let limits = time_control.limits(&board);
+    assert_eq!(limits.move_time, Some(Duration::from_secs(5)));
+    assert_eq!((limits.wtime, limits.depth), (None, Some(8)));
+
+    let board: Board = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1".parse().unwrap();
+    let castle = resolve_move(&board, UserMove::LongCastle).unwrap();
+    assert_eq!(castle, "e1a1".parse().unwrap());
+    assert_eq!(format_move(&board, castle, true), "O-O-O");
+    assert_eq!(format_move(&board, castle, false), "e1a1");
+}
@@ -0,0 +1,43 @@
+//! Black Marlin chess engine
+//!
+//! [Engine] searches positions and reports progress to a [SearchObserver],
+//! the UCI adapter of the BlackMarlin executable is built on the same API.
+//!
+//! ```no_run
+//! use blackmarlin::{Engine, SearchLimits, SearchUpdate};
+//!
+//! let mut engine = Engine::new();
+//! let board = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3"
+//!     .parse()
+//!     .unwrap();
+//! engine.set_position(board, &[]).unwrap();
+//! let limits = SearchLimits {
+//!     depth: Some(10),
+//!     ..SearchLimits::default()
+//! };
+//! let result = engine
+//!     .search(&limits, |update: &SearchUpdate| {
+//!         println!("depth {} score {} pv {:?}", update.depth, update.score, update.pv)
+//!     })
+//!     .unwrap();
+//! match result.best_move {
+//!     Some(best_move) => println!("best move {}", best_move),
+//!     None => println!("no legal moves"),
+//! }
+//! ```
+
+/// Engine internals, not covered by the stability of the public API
+#[doc(hidden)]
+pub mod bm;
+
+pub use cozy_chess;
+
+pub use bm::bm_runner::config::{NoObserver, Score, SearchObserver, SearchResult, SearchUpdate};
+pub use bm::bm_util::position::Aggression;
+pub use bm::bm_util::t_table::Bounds;
+pub use bm::engine::{Engine, IllegalMove, NoSearchMoves, SearchLimits, StopHandle};
+pub use bm::nnue::NnueError;
+
+/// Command line interface of the BlackMarlin executable
+#[doc(hidden)]
+pub use bm::bm_console::BmConsole;
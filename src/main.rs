@@ -1,11 +1,13 @@
-use crate::bm::bm_console::BmConsole;
-
-pub mod bm;
+use blackmarlin::BmConsole;
 
 fn main() {
-    let mut bm_console = BmConsole::new();
+    let json = std::env::args().skip(1).any(|arg| arg == "--json");
+    let mut bm_console = match json {
+        true => BmConsole::json(),
+        false => BmConsole::new(),
+    };
     let mut args = String::new();
-    for arg in std::env::args().skip(1) {
+    for arg in std::env::args().skip(1).filter(|arg| arg != "--json") {
         args.push_str(&arg);
         args.push_str(" ");
     }